@@ -1,3 +1,5 @@
+// Run with `RUSTFLAGS="--cfg nightly" cargo +nightly bench`
+#![cfg(nightly)]
 #![feature(test)]
 
 #[macro_use]
@@ -1,82 +1,208 @@
-#![recursion_limit = "128"]
-
 /*!
 Derive `trait Serializable` for a structure.
 
 Using this crate, it is enough to `#[derive(Serializable)]` on a struct to be able to serialize and
 deserialize it. All the fields must implement [`Serializable`](../npy/trait.Serializable.html).
 
+Tuple structs map to records with the field names `f0`, `f1`, etc., like tuples do in `numpy`.
+A field can be given a different name by `#[npy(rename = "...")]`. Newtypes, i.e. tuple structs
+with a single field, are stored just like their field.
+
+Struct fields can be customized using attributes:
+
+ * `#[npy(rename = "...")]` stores the field under a different name,
+ * `#[npy(skip)]` omits the field from the file; it is set to its `Default` value on read.
+ * `#[npy(default)]` allows the field to be missing when reading by name using
+   [`NpyData::from_bytes_by_name`](../npy/struct.NpyData.html#method.from_bytes_by_name); it is
+   set to its `Default` value then.
+
+The container attribute `#[npy(rename_all = "...")]` renames all the fields according to a case
+convention: `"lowercase"`, `"UPPERCASE"`, `"PascalCase"`, `"camelCase"`, `"snake_case"`,
+`"SCREAMING_SNAKE_CASE"`, `"kebab-case"` or `"SCREAMING-KEBAB-CASE"`.
+
+Generic structs get a `T: Serializable` bound for every type parameter used by the stored fields.
+The container attribute `#[npy(bound = "...")]` replaces these bounds by the given where clause
+predicates, e.g. when a skipped field needs `T: Default`:
+
+```
+# #[macro_use] extern crate npy_derive;
+# extern crate npy;
+#[derive(Serializable)]
+struct Point<T> {
+    x: T,
+    y: T,
+}
+
+#[derive(Serializable)]
+#[npy(bound = "T: npy::Serializable + Default")]
+struct Cached<T> {
+    value: T,
+    #[npy(skip)]
+    previous: T,
+}
+# fn main() {}
+```
+
+C-like enums with an integer `#[repr(...)]` can be derived as well. They map to the integer type
+of the `repr`. Unknown discriminants are read as the unit variant marked with `#[npy(other)]`, and
+strict reading returns an error for them unless there is such a fallback. Without a fallback, the
+lenient reading returns the first variant instead:
+
+```
+# #[macro_use] extern crate npy_derive;
+# extern crate npy;
+#[derive(Serializable)]
+#[repr(u8)]
+enum Label {
+    Cat = 1,
+    Dog = 2,
+    #[npy(other)]
+    Unknown = 255,
+}
+# fn main() {}
+```
+
+Unsupported types and attributes are reported as compile errors pointing at the offending item:
+
+```compile_fail
+# #[macro_use] extern crate npy_derive;
+# extern crate npy;
+#[derive(Serializable)]
+enum Shape {
+    Circle(f64),
+    Square(f64),
+}
+# fn main() {}
+```
+
 */
 
 extern crate proc_macro;
+extern crate proc_macro2;
 extern crate syn;
 #[macro_use]
 extern crate quote;
 
 use proc_macro::TokenStream;
-use syn::Data;
-use quote::{Tokens, ToTokens};
+use proc_macro2::{TokenStream as Tokens, TokenTree};
+use syn::{Data, DataEnum, Fields, Error, Result};
+use syn::ext::IdentExt;
+use syn::punctuated::Punctuated;
+use quote::ToTokens;
 
 /// Macros 1.1-based custom derive function
-#[proc_macro_derive(Serializable)]
+#[proc_macro_derive(Serializable, attributes(npy))]
 pub fn npy_data(input: TokenStream) -> TokenStream {
-    // Construct a string representation of the type definition
-    // let s = input.to_string();
-
     // Parse the string representation
-    let ast = syn::parse(input).unwrap();
+    let ast = syn::parse_macro_input!(input as syn::DeriveInput);
 
-    // Build the impl
-    let expanded = impl_npy_data(&ast);
+    // Build the impl, or the errors pointing to the unsupported constructs
+    let expanded = impl_npy_data(&ast).unwrap_or_else(compile_error);
 
     // Return the generated impl
     expanded.into()
 }
 
-fn impl_npy_data(ast: &syn::DeriveInput) -> quote::Tokens {
+/// Like `Error::into_compile_error`, which refers to `::core` unavailable in the 2015 edition
+fn compile_error(error: Error) -> Tokens {
+    error.into_iter().map(|error| {
+        let message = error.to_string();
+        quote_spanned!(error.span()=> compile_error!(#message);)
+    }).collect()
+}
+
+fn impl_npy_data(ast: &syn::DeriveInput) -> Result<Tokens> {
+    match ast.data {
+        Data::Struct(ref data) => impl_struct(ast, &data.fields),
+        Data::Enum(ref data) => impl_enum(ast, data),
+        Data::Union(ref data) => Err(Error::new(data.union_token.span,
+            "#[derive(Serializable)] can only be used with structs and enums")),
+    }
+}
+
+fn impl_struct(ast: &syn::DeriveInput, fields: &Fields) -> Result<Tokens> {
     let name = &ast.ident;
-    let fields = match ast.data {
-        Data::Struct(ref data) => &data.fields,
-        _ => panic!("#[derive(Serializable)] can only be used with structs"),
-    };
-    // Helper is provided for handling complex generic types correctly and effortlessly
-    let (impl_generics, ty_generics, where_clause) = ast.generics.split_for_impl();
 
-    let idents = fields.iter().map(|f| {
-        let mut t = Tokens::new();
-        f.ident.clone().expect("Tuple structs not supported").to_tokens(&mut t);
-        t
-    }).collect::<Vec<_>>();
-    let types = fields.iter().map(|f|  {
-        let mut t = Tokens::new();
-        f.ty.to_tokens(&mut t);
-        t
-    }).collect::<Vec<_>>();
-
-    let idents_c = idents.clone();
-    let idents_str = idents.clone().into_iter().map(|t| t.to_string()).collect::<Vec<_>>();
-    let idents_str_c1 = idents_str.clone();
-    let types_c1 = types.clone();
-    let types_c2 = types.clone();
-    let types_c3 = types.clone();
-
-    let nats_0 = 0usize..;
-    let nats_1 = 0usize..;
+    if let Fields::Unnamed(ref unnamed) = *fields {
+        if unnamed.unnamed.len() == 1 {
+            return impl_newtype(ast, &unnamed.unnamed[0]);
+        }
+    }
+
+    let container = NpyAttrs::parse(&ast.attrs, &["rename_all", "bound"], "structs")?;
+    let rename_all = container.rename_all;
+    let mut idents = vec![];
+    let mut idents_str = vec![];
+    let mut types = vec![];
+    let mut skipped = vec![];
+    let mut defaults = vec![];
+    for (i, f) in fields.iter().enumerate() {
+        let attrs = NpyAttrs::parse(&f.attrs, &["rename", "skip", "default"], "fields")?;
+        // Tuple struct fields are accessed by their index, e.g. `self.0` or `Name { 0: x }`
+        let ident = match f.ident {
+            Some(ref ident) => ident.to_token_stream(),
+            None => syn::Index::from(i).to_token_stream(),
+        };
+        if attrs.skip {
+            skipped.push(ident);
+            continue;
+        }
+        // Tuple struct fields are named like in Numpy, `f0`, `f1`, etc., unless renamed
+        // Raw identifiers like `r#type` are named without their prefix
+        let field_name = attrs.rename.unwrap_or_else(|| match f.ident {
+            Some(ref ident) => {
+                let ident = ident.unraw().to_string();
+                rename_all.map_or_else(|| ident.clone(), |case| case.apply(&ident))
+            },
+            None => format!("f{}", i),
+        });
+        let ty = &f.ty;
+        if idents_str.contains(&field_name) {
+            let message = format!("duplicate field name {:?}", field_name);
+            return Err(match f.ident {
+                Some(ref ident) => Error::new_spanned(ident, message),
+                None => Error::new_spanned(ty, message),
+            });
+        }
+
+        // Defaults of the field itself, and of its nested fields
+        if attrs.default {
+            defaults.push(quote! {
+                let mut bytes = vec![];
+                <#ty as ::npy::Serializable>::write(&::std::default::Default::default(), &mut bytes).unwrap();
+                defaults.push((vec![#field_name.to_string()], bytes));
+            });
+        }
+        defaults.push(quote! {
+            for (mut path, bytes) in <#ty as ::npy::Serializable>::field_defaults() {
+                path.insert(0, #field_name.to_string());
+                defaults.push((path, bytes));
+            }
+        });
+        idents.push(ident);
+        idents_str.push(field_name);
+        types.push(ty);
+    }
+
+    let generics = with_bounds(&ast.generics, container.bound, &types);
+    // Helper is provided for handling complex generic types correctly and effortlessly
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+    let nats = (0..types.len()).collect::<Vec<_>>();
     let n_fields = types.len();
 
-    quote! {
+    Ok(quote! {
         impl #impl_generics ::npy::Serializable for #name #ty_generics #where_clause {
             fn dtype() -> ::npy::DType {
                 ::npy::DType::Record(vec![#(
                     ::npy::Field {
-                        name: #idents_str_c1.to_string(),
-                        dtype: <#types_c1 as ::npy::Serializable>::dtype()
+                        name: #idents_str.to_string(),
+                        dtype: <#types as ::npy::Serializable>::dtype()
                     }
                 ),*])
             }
 
             fn n_bytes() -> usize {
-                #( <#types_c2 as ::npy::Serializable>::n_bytes() )+*
+                0 #( + <#types as ::npy::Serializable>::n_bytes() )*
             }
 
             #[allow(unused_assignments)]
@@ -84,19 +210,308 @@ fn impl_npy_data(ast: &syn::DeriveInput) -> quote::Tokens {
                 let mut offset = 0;
                 let mut offsets = [0; #n_fields + 1];
                 #(
-                    offset += <#types_c3 as ::npy::Serializable>::n_bytes();
-                    offsets[#nats_0 + 1] = offset;
+                    offset += <#types as ::npy::Serializable>::n_bytes();
+                    offsets[#nats + 1] = offset;
                 )*
 
-                #name { #(
-                    #idents: ::npy::Serializable::read(&buf[offsets[#nats_1]..])
-                ),* }
+                #name {
+                    #( #idents: ::npy::Serializable::read(&buf[offsets[#nats]..]), )*
+                    #( #skipped: ::std::default::Default::default(), )*
+                }
             }
 
             fn write<W: ::std::io::Write>(&self, writer: &mut W) -> ::std::io::Result<()> {
-                #( ::npy::Serializable::write(&self.#idents_c, writer)?; )*
+                #( ::npy::Serializable::write(&self.#idents, writer)?; )*
                 Ok(())
             }
+
+            #[allow(unused_assignments)]
+            fn validate(buf: &[u8]) -> ::std::io::Result<()> {
+                let mut offset = 0;
+                #(
+                    <#types as ::npy::Serializable>::validate(&buf[offset..])?;
+                    offset += <#types as ::npy::Serializable>::n_bytes();
+                )*
+                Ok(())
+            }
+
+            fn field_defaults() -> ::npy::FieldDefaults {
+                let mut defaults = vec![];
+                #( #defaults )*
+                defaults
+            }
+        }
+    })
+}
+
+/// A struct with a single unnamed field is stored just like the field
+fn impl_newtype(ast: &syn::DeriveInput, field: &syn::Field) -> Result<Tokens> {
+    let name = &ast.ident;
+    let ty = &field.ty;
+    let bound = NpyAttrs::parse(&ast.attrs, &["bound"], "newtypes")?.bound;
+    NpyAttrs::parse(&field.attrs, &[], "newtype fields")?;
+    let generics = with_bounds(&ast.generics, bound, &[ty]);
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+
+    Ok(quote! {
+        impl #impl_generics ::npy::Serializable for #name #ty_generics #where_clause {
+            fn dtype() -> ::npy::DType {
+                <#ty as ::npy::Serializable>::dtype()
+            }
+
+            fn n_bytes() -> usize {
+                <#ty as ::npy::Serializable>::n_bytes()
+            }
+
+            fn read(buf: &[u8]) -> Self {
+                #name(::npy::Serializable::read(buf))
+            }
+
+            fn write<W: ::std::io::Write>(&self, writer: &mut W) -> ::std::io::Result<()> {
+                ::npy::Serializable::write(&self.0, writer)
+            }
+
+            fn validate(buf: &[u8]) -> ::std::io::Result<()> {
+                <#ty as ::npy::Serializable>::validate(buf)
+            }
+
+            fn field_defaults() -> ::npy::FieldDefaults {
+                <#ty as ::npy::Serializable>::field_defaults()
+            }
+        }
+    })
+}
+
+fn impl_enum(ast: &syn::DeriveInput, data: &DataEnum) -> Result<Tokens> {
+    let name = &ast.ident;
+    let name_str = name.to_string();
+    let (impl_generics, ty_generics, where_clause) = ast.generics.split_for_impl();
+    NpyAttrs::parse(&ast.attrs, &[], "enums")?;
+    let repr = enum_repr(&ast.attrs)?.ok_or_else(|| Error::new_spanned(name,
+        "#[derive(Serializable)] on enums needs a #[repr(...)] with a fixed-size integer type"))?;
+
+    let mut variants = vec![];
+    let mut fallback = None;
+    for variant in &data.variants {
+        match variant.fields {
+            Fields::Unit => {},
+            ref fields => return Err(Error::new_spanned(fields,
+                "#[derive(Serializable)] can only be used with C-like enums")),
+        }
+        if NpyAttrs::parse(&variant.attrs, &["other"], "enum variants")?.other {
+            if fallback.is_some() {
+                return Err(Error::new_spanned(&variant.ident,
+                    "only one variant can be marked as #[npy(other)]"));
+            }
+            fallback = Some(&variant.ident);
         }
+        variants.push(&variant.ident);
+    }
+
+    let (read_unknown, validate_unknown) = match fallback {
+        Some(v) => (quote! { #name::#v }, quote! { Ok(()) }),
+        None => {
+            let first = &variants[0];
+            (
+                quote! { #name::#first },
+                quote! { Err(::std::io::Error::new(::std::io::ErrorKind::InvalidData,
+                    format!("{} is not a valid discriminant of {}", value, #name_str))) },
+            )
+        },
+    };
+
+    Ok(quote! {
+        impl #impl_generics ::npy::Serializable for #name #ty_generics #where_clause {
+            fn dtype() -> ::npy::DType {
+                <#repr as ::npy::Serializable>::dtype()
+            }
+
+            fn n_bytes() -> usize {
+                <#repr as ::npy::Serializable>::n_bytes()
+            }
+
+            fn read(buf: &[u8]) -> Self {
+                let value = <#repr as ::npy::Serializable>::read(buf);
+                #( if value == #name::#variants as #repr { return #name::#variants; } )*
+                #read_unknown
+            }
+
+            fn write<W: ::std::io::Write>(&self, writer: &mut W) -> ::std::io::Result<()> {
+                let value = match *self { #( #name::#variants => #name::#variants as #repr ),* };
+                ::npy::Serializable::write(&value, writer)
+            }
+
+            fn validate(buf: &[u8]) -> ::std::io::Result<()> {
+                let value = <#repr as ::npy::Serializable>::read(buf);
+                #( if value == #name::#variants as #repr { return Ok(()); } )*
+                #validate_unknown
+            }
+        }
+    })
+}
+
+/// Adds `T: Serializable` for the type parameters used by the stored field `types`, or the
+/// predicates of `#[npy(bound = "...")]` if given
+fn with_bounds(generics: &syn::Generics, bound: Option<Vec<syn::WherePredicate>>, types: &[&syn::Type])
+        -> syn::Generics {
+    let mut generics = generics.clone();
+    let predicates = bound.unwrap_or_else(|| generics.type_params()
+        .filter(|param| types.iter().any(|ty| mentions(ty.to_token_stream(), &param.ident)))
+        .map(|param| {
+            let ident = &param.ident;
+            syn::parse_quote!(#ident: ::npy::Serializable)
+        })
+        .collect());
+    generics.make_where_clause().predicates.extend(predicates);
+    generics
+}
+
+/// Whether the identifier occurs anywhere in the tokens
+fn mentions(tokens: Tokens, ident: &syn::Ident) -> bool {
+    tokens.into_iter().any(|token| match token {
+        TokenTree::Ident(ref token) => token == ident,
+        TokenTree::Group(ref group) => mentions(group.stream(), ident),
+        _ => false,
+    })
+}
+
+/// The integer type of a `#[repr(...)]` attribute
+fn enum_repr(attrs: &[syn::Attribute]) -> Result<Option<syn::Ident>> {
+    for attr in attrs.iter().filter(|attr| attr.path().is_ident("repr")) {
+        let metas = attr.parse_args_with(Punctuated::<syn::Meta, syn::Token![,]>::parse_terminated)?;
+        for meta in metas {
+            if let syn::Meta::Path(ref path) = meta {
+                if let Some(ident) = path.get_ident() {
+                    if ["i8", "i16", "i32", "i64", "u8", "u16", "u32", "u64"].contains(&&*ident.to_string()) {
+                        return Ok(Some(ident.clone()));
+                    }
+                }
+            }
+        }
+    }
+    Ok(None)
+}
+
+/// The options given by `#[npy(...)]` attributes
+#[derive(Default)]
+struct NpyAttrs {
+    /// `#[npy(rename = "...")]`
+    rename: Option<String>,
+    /// `#[npy(rename_all = "...")]`
+    rename_all: Option<Case>,
+    /// `#[npy(bound = "...")]`
+    bound: Option<Vec<syn::WherePredicate>>,
+    /// `#[npy(skip)]`
+    skip: bool,
+    /// `#[npy(default)]`
+    default: bool,
+    /// `#[npy(other)]`
+    other: bool,
+}
+
+impl NpyAttrs {
+    /// Parses all `#[npy(...)]` attributes, allowing only the options in `allowed` on `item`
+    fn parse(attrs: &[syn::Attribute], allowed: &[&str], item: &str) -> Result<NpyAttrs> {
+        let mut result = NpyAttrs::default();
+        for attr in attrs.iter().filter(|attr| attr.path().is_ident("npy")) {
+            attr.parse_nested_meta(|meta| {
+                let option = meta.path.to_token_stream().to_string().replace(' ', "");
+                if !allowed.contains(&&*option) {
+                    return Err(match &*option {
+                        "rename" | "rename_all" | "bound" | "skip" | "default" | "other" =>
+                            meta.error(format!("#[npy({})] can't be used on {}", option, item)),
+                        _ => meta.error(format!("unknown attribute #[npy({})]", option)),
+                    });
+                }
+                match &*option {
+                    "rename" => {
+                        let name: syn::LitStr = meta.value()?.parse()?;
+                        result.rename = Some(name.value());
+                    },
+                    "rename_all" => {
+                        let case: syn::LitStr = meta.value()?.parse()?;
+                        result.rename_all = Some(Case::from_str(&case.value()).ok_or_else(|| Error::new_spanned(&case,
+                            format!("unknown case convention {:?} in #[npy(rename_all = ...)]", case.value())))?);
+                    },
+                    "bound" => {
+                        let bound: syn::LitStr = meta.value()?.parse()?;
+                        let predicates = bound.parse_with(
+                            Punctuated::<syn::WherePredicate, syn::Token![,]>::parse_terminated)?;
+                        result.bound = Some(predicates.into_iter().collect());
+                    },
+                    "skip" => result.skip = true,
+                    "default" => result.default = true,
+                    "other" => result.other = true,
+                    _ => unreachable!(),
+                }
+                Ok(())
+            })?;
+        }
+        Ok(result)
+    }
+}
+
+/// Case conventions for renaming `snake_case` field names, as in `serde`
+#[derive(Clone, Copy)]
+enum Case {
+    Lower,
+    Upper,
+    Pascal,
+    Camel,
+    Snake,
+    ScreamingSnake,
+    Kebab,
+    ScreamingKebab,
+}
+
+impl Case {
+    fn from_str(case: &str) -> Option<Case> {
+        match case {
+            "lowercase" => Some(Case::Lower),
+            "UPPERCASE" => Some(Case::Upper),
+            "PascalCase" => Some(Case::Pascal),
+            "camelCase" => Some(Case::Camel),
+            "snake_case" => Some(Case::Snake),
+            "SCREAMING_SNAKE_CASE" => Some(Case::ScreamingSnake),
+            "kebab-case" => Some(Case::Kebab),
+            "SCREAMING-KEBAB-CASE" => Some(Case::ScreamingKebab),
+            _ => None,
+        }
+    }
+
+    fn apply(self, field: &str) -> String {
+        let capitalize = |word: &str| {
+            let mut chars = word.chars();
+            chars.next().map_or(String::new(), |c| c.to_uppercase().chain(chars).collect())
+        };
+        let words = field.split('_').filter(|word| !word.is_empty());
+        match self {
+            Case::Lower | Case::Snake => field.to_string(),
+            Case::Upper | Case::ScreamingSnake => field.to_uppercase(),
+            Case::Pascal => words.map(capitalize).collect(),
+            Case::Camel => words.enumerate()
+                .map(|(i, word)| if i == 0 { word.to_string() } else { capitalize(word) })
+                .collect(),
+            Case::Kebab => field.replace('_', "-"),
+            Case::ScreamingKebab => field.replace('_', "-").to_uppercase(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn renames_snake_case_fields() {
+        let rename = |case| Case::from_str(case).unwrap().apply("time_stamp_2");
+        assert_eq!(rename("lowercase"), "time_stamp_2");
+        assert_eq!(rename("UPPERCASE"), "TIME_STAMP_2");
+        assert_eq!(rename("PascalCase"), "TimeStamp2");
+        assert_eq!(rename("camelCase"), "timeStamp2");
+        assert_eq!(rename("SCREAMING_SNAKE_CASE"), "TIME_STAMP_2");
+        assert_eq!(rename("kebab-case"), "time-stamp-2");
+        assert_eq!(rename("SCREAMING-KEBAB-CASE"), "TIME-STAMP-2");
+        assert!(Case::from_str("Title Case").is_none());
     }
 }
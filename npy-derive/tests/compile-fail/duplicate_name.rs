@@ -0,0 +1,12 @@
+#[macro_use]
+extern crate npy_derive;
+extern crate npy;
+
+#[derive(Serializable)]
+struct Point {
+    #[npy(rename = "x")]
+    a: u8,
+    x: u8,
+}
+
+fn main() {}
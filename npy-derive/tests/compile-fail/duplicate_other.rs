@@ -0,0 +1,15 @@
+#[macro_use]
+extern crate npy_derive;
+extern crate npy;
+
+#[derive(Serializable)]
+#[repr(u8)]
+enum Label {
+    Cat,
+    #[npy(other)]
+    Dog,
+    #[npy(other)]
+    Unknown,
+}
+
+fn main() {}
@@ -0,0 +1,12 @@
+#[macro_use]
+extern crate npy_derive;
+extern crate npy;
+
+#[derive(Serializable)]
+#[repr(u8)]
+enum Shape {
+    Point,
+    Circle { radius: f64 },
+}
+
+fn main() {}
@@ -0,0 +1,12 @@
+#[macro_use]
+extern crate npy_derive;
+extern crate npy;
+
+#[derive(Serializable)]
+#[repr(usize)]
+enum Label {
+    Cat,
+    Dog,
+}
+
+fn main() {}
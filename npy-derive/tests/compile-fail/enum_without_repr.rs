@@ -0,0 +1,11 @@
+#[macro_use]
+extern crate npy_derive;
+extern crate npy;
+
+#[derive(Serializable)]
+enum Label {
+    Cat,
+    Dog,
+}
+
+fn main() {}
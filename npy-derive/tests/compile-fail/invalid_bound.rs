@@ -0,0 +1,12 @@
+#[macro_use]
+extern crate npy_derive;
+extern crate npy;
+
+#[derive(Serializable)]
+#[npy(bound = "T")]
+struct Point<T> {
+    x: T,
+    y: T,
+}
+
+fn main() {}
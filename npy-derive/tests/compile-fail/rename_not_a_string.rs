@@ -0,0 +1,12 @@
+#[macro_use]
+extern crate npy_derive;
+extern crate npy;
+
+#[derive(Serializable)]
+struct Point {
+    #[npy(rename = 1)]
+    x: f64,
+    y: f64,
+}
+
+fn main() {}
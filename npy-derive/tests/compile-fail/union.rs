@@ -0,0 +1,11 @@
+#[macro_use]
+extern crate npy_derive;
+extern crate npy;
+
+#[derive(Serializable)]
+union Number {
+    int: i32,
+    float: f32,
+}
+
+fn main() {}
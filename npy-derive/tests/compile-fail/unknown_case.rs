@@ -0,0 +1,12 @@
+#[macro_use]
+extern crate npy_derive;
+extern crate npy;
+
+#[derive(Serializable)]
+#[npy(rename_all = "Title Case")]
+struct Point {
+    x: f64,
+    y: f64,
+}
+
+fn main() {}
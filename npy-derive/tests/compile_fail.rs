@@ -0,0 +1,7 @@
+extern crate trybuild;
+
+#[test]
+fn unsupported_constructs() {
+    let t = trybuild::TestCases::new();
+    t.compile_fail("tests/compile-fail/*.rs");
+}
@@ -0,0 +1,73 @@
+use std::io::{Write, Result};
+use byteorder::{WriteBytesExt, LittleEndian, ByteOrder};
+
+use header::DType;
+use serializable::Serializable;
+
+/// A complex number, stored as a pair of its real and imaginary part.
+///
+/// `Complex<f32>` maps to Numpy's `complex64` and `Complex<f64>` to `complex128`. This type is
+/// available without any dependencies; with the `num-complex` feature enabled,
+/// `num_complex::Complex` can be used directly instead.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+#[repr(C)]
+pub struct Complex<T> {
+    /// Real part
+    pub re: T,
+    /// Imaginary part
+    pub im: T,
+}
+
+impl<T> Complex<T> {
+    /// Create a complex number from its real and imaginary part
+    pub fn new(re: T, im: T) -> Self {
+        Complex { re, im }
+    }
+}
+
+impl<T> From<[T; 2]> for Complex<T> {
+    fn from([re, im]: [T; 2]) -> Self {
+        Complex { re, im }
+    }
+}
+
+impl<T> From<Complex<T>> for [T; 2] {
+    fn from(c: Complex<T>) -> Self {
+        [c.re, c.im]
+    }
+}
+
+macro_rules! gen_complex_serializable {
+    ($complex:ident, $float:ident, $ty:expr, $read:ident, $write:ident) => {
+        impl Serializable for $complex<$float> {
+            #[inline]
+            fn dtype() -> DType {
+                DType::Plain { ty: $ty.to_string(), shape: vec![] }
+            }
+            #[inline]
+            fn n_bytes() -> usize { 2 * ::std::mem::size_of::<$float>() }
+            #[inline]
+            fn read(buf: &[u8]) -> Self {
+                let n = ::std::mem::size_of::<$float>();
+                $complex { re: LittleEndian::$read(buf), im: LittleEndian::$read(&buf[n..]) }
+            }
+            #[inline]
+            fn write<W: Write>(&self, writer: &mut W) -> Result<()> {
+                writer.$write::<LittleEndian>(self.re)?;
+                writer.$write::<LittleEndian>(self.im)
+            }
+        }
+    }
+}
+
+gen_complex_serializable!(Complex, f32, "<c8", read_f32, write_f32);
+gen_complex_serializable!(Complex, f64, "<c16", read_f64, write_f64);
+
+#[cfg(feature = "num-complex")]
+mod num {
+    use super::*;
+    use num_complex::Complex;
+
+    gen_complex_serializable!(Complex, f32, "<c8", read_f32, write_f32);
+    gen_complex_serializable!(Complex, f64, "<c16", read_f64, write_f64);
+}
@@ -0,0 +1,404 @@
+use std::convert::TryFrom;
+use std::io::{Result, ErrorKind, Error};
+
+use header::{shape_size, DType, Field};
+use type_str::{split_byte_order, native_byte_order, swap_unit};
+use datetime::unsupported_time_unit;
+
+/// Instructions for converting a record stored with the dtype of a file into the layout of the
+/// dtype expected by [`Serializable::read`](trait.Serializable.html#tymethod.read).
+///
+/// This allows reading data which is compatible with a `Serializable` type, but not stored in
+/// exactly the same way, e.g. in a different byte order.
+pub struct Conversion {
+    ops: Vec<Op>,
+    fills: Vec<(usize, Vec<u8>)>,
+    src_size: usize,
+    dst_size: usize,
+}
+
+/// Copy `len` bytes, reversing the byte order of every `unit` bytes
+#[derive(PartialEq, Eq, Debug)]
+struct Op {
+    src: usize,
+    dst: usize,
+    len: usize,
+    unit: usize,
+}
+
+/// Serialized default values of record fields, by the path of field names leading to them
+pub type FieldDefaults = Vec<(Vec<String>, Vec<u8>)>;
+
+impl Conversion {
+    /// Plan the conversion of records of dtype `from` into records of dtype `to`. Fails if the
+    /// dtypes are not compatible.
+    pub fn new(from: &DType, to: &DType) -> Result<Conversion> {
+        Planner { by_name: false, defaults: &[], path: vec![], ops: vec![], fills: vec![] }
+            .finish(from, to)
+    }
+
+    /// Plan the conversion like [`new`](#method.new), but match record fields by their names.
+    ///
+    /// Fields of `from` which are not in `to` are skipped, and the order of the fields may
+    /// differ. Fields of `to` which are missing in `from` are filled in from `defaults`, or the
+    /// planning fails if there is no default.
+    pub fn by_name(from: &DType, to: &DType, defaults: &FieldDefaults) -> Result<Conversion> {
+        Planner { by_name: true, defaults, path: vec![], ops: vec![], fills: vec![] }
+            .finish(from, to)
+    }
+
+    /// Whether the conversion is a plain copy, so the source can be read directly
+    pub fn is_identity(&self) -> bool {
+        self.src_size == self.dst_size && self.fills.is_empty() &&
+            self.ops.iter().all(|op| op.unit == 1 && op.src == op.dst) &&
+            self.ops.iter().map(|op| op.len).sum::<usize>() == self.dst_size
+    }
+
+    /// Number of bytes of a record in the source layout
+    pub fn src_size(&self) -> usize {
+        self.src_size
+    }
+
+    /// Number of bytes of a record in the destination layout
+    pub fn dst_size(&self) -> usize {
+        self.dst_size
+    }
+
+    /// Convert a single record from `src` into `dst`
+    pub fn apply(&self, src: &[u8], dst: &mut [u8]) {
+        for (offset, bytes) in &self.fills {
+            dst[*offset..*offset + bytes.len()].copy_from_slice(bytes);
+        }
+        for op in &self.ops {
+            let src = &src[op.src..op.src + op.len];
+            let dst = &mut dst[op.dst..op.dst + op.len];
+            if op.unit == 1 {
+                dst.copy_from_slice(src);
+            } else {
+                for (s, d) in src.chunks(op.unit).zip(dst.chunks_mut(op.unit)) {
+                    for (a, b) in s.iter().rev().zip(d) {
+                        *b = *a;
+                    }
+                }
+            }
+        }
+    }
+}
+
+struct Planner<'a> {
+    by_name: bool,
+    defaults: &'a [(Vec<String>, Vec<u8>)],
+    /// Names of the record fields leading to the currently planned field
+    path: Vec<String>,
+    ops: Vec<Op>,
+    fills: Vec<(usize, Vec<u8>)>,
+}
+
+impl<'a> Planner<'a> {
+    fn finish(mut self, from: &DType, to: &DType) -> Result<Conversion> {
+        self.plan(from, 0, to, 0)?;
+        Ok(Conversion {
+            ops: self.ops,
+            fills: self.fills,
+            src_size: from.n_bytes()?,
+            dst_size: to.n_bytes()?,
+        })
+    }
+
+    fn plan(&mut self, from: &DType, src: usize, to: &DType, dst: usize) -> Result<()> {
+        use DType::*;
+        if let (Some(from_layout), Some(to_layout)) = (record_layout(from)?, record_layout(to)?) {
+            return self.plan_fields(&from_layout, src, &to_layout, dst);
+        }
+        match (from, to) {
+            (Plain { ty: from_ty, shape: from_shape }, Plain { ty: to_ty, shape: to_shape }) => {
+                let (from_order, from_ty) = split_byte_order(from_ty);
+                let (to_order, to_ty) = split_byte_order(to_ty);
+                if let Some(unit) = unsupported_time_unit(from_ty) {
+                    return incompatible(&format!("unsupported datetime unit '{}' in '{}'", unit, from_ty));
+                }
+                if from_ty != to_ty || from_shape != to_shape {
+                    return incompatible(&format!("'{}' can't be read as '{}'", from_ty, to_ty));
+                }
+                let len = from.n_bytes()?;
+                let unit = match swap_unit(from_ty)? {
+                    // The byte order is irrelevant for single bytes
+                    1 => 1,
+                    unit => match (native_byte_order(from_order), native_byte_order(to_order)) {
+                        (Some(from_order), Some(to_order)) => if from_order == to_order { 1 } else { unit },
+                        _ => return incompatible(&format!("'{}' needs a byte order", from_ty)),
+                    },
+                };
+                push_op(&mut self.ops, Op { src, dst, len, unit });
+                Ok(())
+            },
+            (RecordArray { record: from_record, shape: from_shape },
+             RecordArray { record: to_record, shape: to_shape }) => {
+                if from_shape != to_shape {
+                    return incompatible("the shapes of the record arrays don't match");
+                }
+                let (from_size, to_size) = (from_record.n_bytes()?, to_record.n_bytes()?);
+                for i in 0..shape_size(from_shape)? {
+                    self.plan(from_record, src + i * from_size, to_record, dst + i * to_size)?;
+                }
+                Ok(())
+            },
+            (Plain { .. }, _) | (_, Plain { .. }) =>
+                incompatible("a plain type can't be read as a record or vice versa"),
+            _ => incompatible("a record can't be read as an array of records or vice versa"),
+        }
+    }
+
+    /// Plan the fields of records, given as the fields in their logical order with their offsets
+    fn plan_fields(&mut self, from_fields: &[(usize, &Field)], src: usize,
+                   to_fields: &[(usize, &Field)], dst: usize) -> Result<()> {
+        if self.by_name {
+            return self.plan_fields_by_name(from_fields, src, to_fields, dst);
+        }
+        let mut to_fields = to_fields.iter().peekable();
+        for &(from_offset, from_field) in from_fields {
+            // Padding is skipped, unless it's expected to be read
+            if from_field.is_padding() && !to_fields.peek().is_some_and(|&&(_, f)| f.is_padding()) {
+                continue;
+            }
+            let (to_offset, to_field) = match to_fields.next() {
+                Some(&to_field) => to_field,
+                None => return incompatible("the number of fields doesn't match"),
+            };
+            if from_field.name != to_field.name {
+                return incompatible(&format!("field '{}' can't be read as '{}'",
+                                             from_field.name, to_field.name));
+            }
+            self.plan(&from_field.dtype, src + from_offset, &to_field.dtype, dst + to_offset)?;
+        }
+        if to_fields.next().is_some() {
+            return incompatible("the number of fields doesn't match");
+        }
+        Ok(())
+    }
+
+    fn plan_fields_by_name(&mut self, from_fields: &[(usize, &Field)], src: usize,
+                           to_fields: &[(usize, &Field)], dst: usize) -> Result<()> {
+        for &(to_offset, to_field) in to_fields {
+            let dst = dst + to_offset;
+            self.path.push(to_field.name.clone());
+            let from = from_fields.iter()
+                .find(|&&(_, from_field)| from_field.name == to_field.name && !from_field.is_padding());
+            match from {
+                Some(&(from_offset, from_field)) =>
+                    self.plan(&from_field.dtype, src + from_offset, &to_field.dtype, dst)?,
+                // Expected padding is left zeroed
+                None if to_field.is_padding() => {},
+                None => match self.defaults.iter().find(|&(path, _)| *path == self.path) {
+                    Some((_, bytes)) if bytes.len() == to_field.dtype.n_bytes()? =>
+                        self.fills.push((dst, bytes.clone())),
+                    Some(_) => return incompatible(&format!(
+                        "the default value of field '{}' has a wrong size", self.path.join("."))),
+                    None => return incompatible(&format!(
+                        "field '{}' is missing and has no default", self.path.join("."))),
+                },
+            }
+            self.path.pop();
+        }
+        Ok(())
+    }
+}
+
+/// The fields of a record in their logical order together with their offsets, or `None` if the
+/// dtype is not a record
+fn record_layout(dtype: &DType) -> Result<Option<Vec<(usize, &Field)>>> {
+    match *dtype {
+        DType::Record(ref fields) => Ok(Some(packed_layout(fields)?)),
+        DType::OffsetRecord { ref fields, ref offsets, .. } => {
+            let layout = offsets.iter().zip(fields)
+                .map(|(&offset, field)| usize::try_from(offset).map(|offset| (offset, field)))
+                .collect::<::std::result::Result<_, _>>();
+            layout.map(Some).or_else(|_| incompatible("the offsets are too large"))
+        },
+        DType::Plain { .. } | DType::RecordArray { .. } => Ok(None),
+    }
+}
+
+/// The fields stored one after another, together with their offsets
+fn packed_layout(fields: &[Field]) -> Result<Vec<(usize, &Field)>> {
+    let mut layout = vec![];
+    let mut offset = 0;
+    for field in fields {
+        layout.push((offset, field));
+        offset += field.dtype.n_bytes()?;
+    }
+    Ok(layout)
+}
+
+/// Add an operation, merging it into the previous one if they are contiguous plain copies
+fn push_op(ops: &mut Vec<Op>, op: Op) {
+    if let Some(last) = ops.last_mut() {
+        if last.unit == 1 && op.unit == 1 && last.src + last.len == op.src && last.dst + last.len == op.dst {
+            last.len += op.len;
+            return;
+        }
+    }
+    ops.push(op);
+}
+
+fn incompatible<T>(message: &str) -> Result<T> {
+    Err(Error::new(ErrorKind::InvalidData, message.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn plain(ty: &str) -> DType {
+        DType::Plain { ty: ty.to_string(), shape: vec![] }
+    }
+
+    fn record(fields: &[(&str, DType)]) -> DType {
+        DType::Record(fields.iter().map(|&(name, ref dtype)| Field {
+            name: name.to_string(),
+            dtype: dtype.clone(),
+        }).collect())
+    }
+
+    fn convert(from: &DType, to: &DType, src: &[u8]) -> Vec<u8> {
+        apply(&Conversion::new(from, to).unwrap(), src)
+    }
+
+    fn apply(conversion: &Conversion, src: &[u8]) -> Vec<u8> {
+        let mut dst = vec![0; conversion.dst_size()];
+        conversion.apply(src, &mut dst);
+        dst
+    }
+
+    #[test]
+    fn swaps_bytes_of_big_endian_types() {
+        assert_eq!(convert(&plain(">i4"), &plain("<i4"), &[1, 2, 3, 4]), vec![4, 3, 2, 1]);
+        assert_eq!(convert(&plain("<i4"), &plain("<i4"), &[1, 2, 3, 4]), vec![1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn swaps_bytes_of_each_array_item() {
+        let from = DType::Plain { ty: ">u2".to_string(), shape: vec![3] };
+        let to = DType::Plain { ty: "<u2".to_string(), shape: vec![3] };
+        assert_eq!(convert(&from, &to, &[1, 2, 3, 4, 5, 6]), vec![2, 1, 4, 3, 6, 5]);
+    }
+
+    #[test]
+    fn swaps_bytes_of_each_record_field() {
+        let from = record(&[("a", plain(">u2")), ("b", plain("<u2")), ("c", record(&[("d", plain(">u4"))]))]);
+        let to = record(&[("a", plain("<u2")), ("b", plain("<u2")), ("c", record(&[("d", plain("<u4"))]))]);
+        assert_eq!(convert(&from, &to, &[1, 2, 3, 4, 5, 6, 7, 8]), vec![2, 1, 3, 4, 8, 7, 6, 5]);
+    }
+
+    #[test]
+    fn ignores_byte_order_of_single_bytes() {
+        for &(from, to) in &[("|u1", "<u1"), ("<i1", ">i1"), ("|b1", "=b1"), ("|S3", "<S3")] {
+            let conversion = Conversion::new(&plain(from), &plain(to)).unwrap();
+            assert!(conversion.is_identity());
+        }
+    }
+
+    #[test]
+    fn resolves_native_byte_order() {
+        let (native, foreign) = if cfg!(target_endian = "little") { ("<f8", ">f8") } else { (">f8", "<f8") };
+        assert!(Conversion::new(&plain("=f8"), &plain(native)).unwrap().is_identity());
+        assert!(Conversion::new(&plain("f8"), &plain(native)).unwrap().is_identity());
+        assert!(!Conversion::new(&plain("=f8"), &plain(foreign)).unwrap().is_identity());
+    }
+
+    #[test]
+    fn errors_on_missing_byte_order_of_multibyte_types() {
+        assert!(Conversion::new(&plain("|f8"), &plain("<f8")).is_err());
+    }
+
+    #[test]
+    fn skips_padding() {
+        let from = record(&[("a", plain("|u1")), ("", plain("|V3")), ("b", plain(">u2")), ("", plain("|V2"))]);
+        let to = record(&[("a", plain("|u1")), ("b", plain("<u2"))]);
+        let conversion = Conversion::new(&from, &to).unwrap();
+        assert_eq!(conversion.src_size(), 8);
+        assert_eq!(convert(&from, &to, &[1, 0, 0, 0, 2, 3, 0, 0]), vec![1, 3, 2]);
+    }
+
+    #[test]
+    fn reads_fields_at_their_offsets() {
+        let from = DType::OffsetRecord {
+            fields: vec![Field { name: "b".to_string(), dtype: plain("<u2") }, Field { name: "a".to_string(), dtype: plain("|u1") }],
+            offsets: vec![2, 0],
+            itemsize: 6,
+        };
+        let to = record(&[("b", plain("<u2")), ("a", plain("|u1"))]);
+        let conversion = Conversion::new(&from, &to).unwrap();
+        assert_eq!(conversion.src_size(), 6);
+        assert_eq!(convert(&from, &to, &[1, 0, 2, 3, 0, 0]), vec![2, 3, 1]);
+        assert!(Conversion::new(&from, &record(&[("a", plain("|u1")), ("b", plain("<u2"))])).is_err());
+        assert_eq!(convert(&to, &from, &[2, 3, 1]), vec![1, 0, 2, 3, 0, 0]);
+    }
+
+    #[test]
+    fn reads_fields_at_their_offsets_in_each_record_of_subarray() {
+        let from = DType::RecordArray {
+            record: Box::new(DType::OffsetRecord {
+                fields: vec![Field { name: "b".to_string(), dtype: plain("|u1") }, Field { name: "a".to_string(), dtype: plain("|u1") }],
+                offsets: vec![1, 0],
+                itemsize: 3,
+            }),
+            shape: vec![2],
+        };
+        let to = DType::RecordArray { record: Box::new(record(&[("b", plain("|u1")), ("a", plain("|u1"))])), shape: vec![2] };
+        assert_eq!(convert(&from, &to, &[1, 2, 0, 3, 4, 0]), vec![2, 1, 4, 3]);
+    }
+
+    #[test]
+    fn reads_padding_if_expected() {
+        let from = record(&[("a", plain("|u1")), ("", plain("|V1"))]);
+        assert_eq!(convert(&from, &from, &[1, 2]), vec![1, 2]);
+    }
+
+    #[test]
+    fn swaps_bytes_of_each_record_of_subarray() {
+        let from = DType::RecordArray { record: Box::new(record(&[("a", plain(">u2"))])), shape: vec![2] };
+        let to = DType::RecordArray { record: Box::new(record(&[("a", plain("<u2"))])), shape: vec![2] };
+        assert_eq!(convert(&from, &to, &[1, 2, 3, 4]), vec![2, 1, 4, 3]);
+    }
+
+    #[test]
+    fn merges_contiguous_copies() {
+        let dtype = record(&[("a", plain("<u2")), ("b", plain("<f8"))]);
+        let conversion = Conversion::new(&dtype, &dtype).unwrap();
+        assert_eq!(conversion.ops, vec![Op { src: 0, dst: 0, len: 10, unit: 1 }]);
+    }
+
+    #[test]
+    fn errors_on_incompatible_types() {
+        assert!(Conversion::new(&plain("<i4"), &plain("<u4")).is_err());
+        assert!(Conversion::new(&plain("<i4"), &record(&[("a", plain("<i4"))])).is_err());
+        assert!(Conversion::new(&record(&[("a", plain("<i4"))]), &record(&[("b", plain("<i4"))])).is_err());
+        let array = DType::Plain { ty: "<i4".to_string(), shape: vec![2] };
+        assert!(Conversion::new(&array, &plain("<i4")).is_err());
+    }
+
+    #[test]
+    fn matches_fields_by_name() {
+        let from = record(&[("c", plain("<u2")), ("a", plain("|u1")), ("b", record(&[("x", plain(">u2"))]))]);
+        let to = record(&[("a", plain("|u1")), ("b", record(&[("x", plain("<u2"))]))]);
+        assert!(Conversion::new(&from, &to).is_err());
+        let conversion = Conversion::by_name(&from, &to, &vec![]).unwrap();
+        assert_eq!(apply(&conversion, &[1, 2, 3, 4, 5]), vec![3, 5, 4]);
+    }
+
+    #[test]
+    fn fills_missing_fields_by_name_with_defaults() {
+        let from = record(&[("b", record(&[]))]);
+        let to = record(&[("a", plain("|u1")), ("b", record(&[("x", plain("<u2"))]))]);
+        assert!(Conversion::by_name(&from, &to, &vec![(vec!["a".to_string()], vec![7])]).is_err());
+
+        let defaults = vec![
+            (vec!["a".to_string()], vec![7]),
+            (vec!["b".to_string(), "x".to_string()], vec![8, 9]),
+        ];
+        let conversion = Conversion::by_name(&from, &to, &defaults).unwrap();
+        assert!(!conversion.is_identity());
+        assert_eq!(apply(&conversion, &[]), vec![7, 8, 9]);
+    }
+}
@@ -0,0 +1,251 @@
+use std::fmt;
+use std::marker::PhantomData;
+use std::io::{Write, Result};
+use byteorder::{WriteBytesExt, LittleEndian, ByteOrder};
+
+use header::DType;
+use serializable::Serializable;
+
+/// The value Numpy uses to represent "not a time"
+const NAT: i64 = i64::MIN;
+
+/// A unit of time of Numpy's `datetime64` and `timedelta64` types, such as `ns` in `<M8[ns]`.
+///
+/// The unit is a part of the type string, so a file is only read if its unit matches exactly.
+/// Years (`Y`), months (`M`), units shorter than nanoseconds (`ps`, `fs` and `as`) and multiples
+/// of units, such as `10ms`, are not supported, and reading them returns an error.
+pub trait TimeUnit {
+    /// The unit code used in the type string
+    const CODE: &'static str;
+    /// Number of nanoseconds in the unit
+    const NANOS: i64;
+}
+
+macro_rules! gen_time_units {
+    ($($(#[$meta:meta])* $unit:ident: $code:expr, $nanos:expr;)+) => {
+        $(
+            $(#[$meta])*
+            #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+            pub struct $unit;
+
+            impl TimeUnit for $unit {
+                const CODE: &'static str = $code;
+                const NANOS: i64 = $nanos;
+            }
+        )+
+
+        /// Codes of all the units implementing `TimeUnit`
+        const UNIT_CODES: &[&str] = &[$($code),+];
+    }
+}
+
+/// The unit of a `datetime64` or `timedelta64` type string without the byte order, e.g. `Y` in
+/// `M8[Y]`, if there is no `TimeUnit` for it. Generic types without a unit return `""`.
+pub fn unsupported_time_unit(ty: &str) -> Option<&str> {
+    if !ty.starts_with("M8") && !ty.starts_with("m8") {
+        return None;
+    }
+    let unit = ty[2..].strip_prefix('[').and_then(|unit| unit.strip_suffix(']')).unwrap_or("");
+    if UNIT_CODES.contains(&unit) { None } else { Some(unit) }
+}
+
+gen_time_units! {
+    /// Weeks, `[W]`
+    Weeks: "W", 7 * 24 * 3600 * 1_000_000_000;
+    /// Days, `[D]`
+    Days: "D", 24 * 3600 * 1_000_000_000;
+    /// Hours, `[h]`
+    Hours: "h", 3600 * 1_000_000_000;
+    /// Minutes, `[m]`
+    Minutes: "m", 60 * 1_000_000_000;
+    /// Seconds, `[s]`
+    Seconds: "s", 1_000_000_000;
+    /// Milliseconds, `[ms]`
+    Milliseconds: "ms", 1_000_000;
+    /// Microseconds, `[us]`
+    Microseconds: "us", 1_000;
+    /// Nanoseconds, `[ns]`
+    Nanoseconds: "ns", 1;
+}
+
+macro_rules! gen_time_type {
+    ($(#[$meta:meta])* $name:ident, $kind:expr) => {
+        $(#[$meta])*
+        #[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+        pub struct $name<U> {
+            value: i64,
+            _unit: PhantomData<U>,
+        }
+
+        impl<U: TimeUnit> $name<U> {
+            /// "Not a time", Numpy's missing value
+            pub const NAT: Self = $name { value: NAT, _unit: PhantomData };
+
+            /// Create the value from a number of units. `i64::MIN` is interpreted as `NaT`.
+            pub fn new(value: i64) -> Self {
+                $name { value, _unit: PhantomData }
+            }
+
+            /// Number of units, or `None` for `NaT`
+            pub fn get(&self) -> Option<i64> {
+                if self.is_nat() { None } else { Some(self.value) }
+            }
+
+            /// Whether the value is "not a time"
+            pub fn is_nat(&self) -> bool {
+                self.value == NAT
+            }
+        }
+
+        impl<U: TimeUnit> From<Option<i64>> for $name<U> {
+            fn from(value: Option<i64>) -> Self {
+                value.map_or(Self::NAT, Self::new)
+            }
+        }
+
+        impl<U: TimeUnit> fmt::Debug for $name<U> {
+            fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                match self.get() {
+                    Some(value) => write!(f, "{}({}[{}])", stringify!($name), value, U::CODE),
+                    None => write!(f, "{}(NaT)", stringify!($name)),
+                }
+            }
+        }
+
+        impl<U: TimeUnit> Serializable for $name<U> {
+            #[inline]
+            fn dtype() -> DType {
+                DType::Plain { ty: format!("<{}8[{}]", $kind, U::CODE), shape: vec![] }
+            }
+            #[inline]
+            fn n_bytes() -> usize { 8 }
+            #[inline]
+            fn read(buf: &[u8]) -> Self {
+                Self::new(LittleEndian::read_i64(buf))
+            }
+            #[inline]
+            fn write<W: Write>(&self, writer: &mut W) -> Result<()> {
+                writer.write_i64::<LittleEndian>(self.value)
+            }
+        }
+    }
+}
+
+gen_time_type!(
+    /// A point in time, mapping to Numpy's `datetime64` with the unit `U`, e.g.
+    /// `DateTime64<Nanoseconds>` is `<M8[ns]`.
+    ///
+    /// The value is the number of units since 1970-01-01T00:00:00. With the `chrono` feature,
+    /// it can be converted to and from `chrono::NaiveDateTime`.
+    DateTime64, 'M');
+
+gen_time_type!(
+    /// A time duration, mapping to Numpy's `timedelta64` with the unit `U`, e.g.
+    /// `TimeDelta64<Microseconds>` is `<m8[us]`.
+    ///
+    /// With the `chrono` feature, it can be converted to and from `chrono::TimeDelta`.
+    TimeDelta64, 'm');
+
+#[cfg(feature = "chrono")]
+mod chrono_conversions {
+    use super::*;
+    use chrono::{DateTime, NaiveDateTime, TimeDelta};
+
+    const NANOS_PER_SEC: i128 = 1_000_000_000;
+
+    /// Split a number of units into seconds and non-negative nanoseconds
+    fn to_secs<U: TimeUnit>(value: i64) -> Option<(i64, u32)> {
+        let nanos = value as i128 * U::NANOS as i128;
+        let secs = nanos.div_euclid(NANOS_PER_SEC);
+        if secs < i64::MIN as i128 || secs > i64::MAX as i128 {
+            return None;
+        }
+        Some((secs as i64, nanos.rem_euclid(NANOS_PER_SEC) as u32))
+    }
+
+    /// Convert seconds and nanoseconds into units, rounding down. `None` if out of range.
+    fn from_secs<U: TimeUnit>(secs: i64, nanos: i64) -> Option<i64> {
+        let value = (secs as i128 * NANOS_PER_SEC + nanos as i128).div_euclid(U::NANOS as i128);
+        if value <= NAT as i128 || value > i64::MAX as i128 { None } else { Some(value as i64) }
+    }
+
+    impl<U: TimeUnit> DateTime64<U> {
+        /// Convert to a `chrono` date and time. Returns `None` for `NaT` or if out of range.
+        pub fn to_chrono(&self) -> Option<NaiveDateTime> {
+            let (secs, nanos) = to_secs::<U>(self.get()?)?;
+            DateTime::from_timestamp(secs, nanos).map(|t| t.naive_utc())
+        }
+
+        /// Convert from a `chrono` date and time, rounding down to whole units. Returns `None`
+        /// if out of range.
+        pub fn from_chrono(time: &NaiveDateTime) -> Option<Self> {
+            let time = time.and_utc();
+            from_secs::<U>(time.timestamp(), time.timestamp_subsec_nanos() as i64).map(Self::new)
+        }
+    }
+
+    impl<U: TimeUnit> TimeDelta64<U> {
+        /// Convert to a `chrono` duration. Returns `None` for `NaT` or if out of range.
+        pub fn to_chrono(&self) -> Option<TimeDelta> {
+            let (secs, nanos) = to_secs::<U>(self.get()?)?;
+            TimeDelta::new(secs, nanos)
+        }
+
+        /// Convert from a `chrono` duration, rounding down to whole units. Returns `None` if
+        /// out of range.
+        pub fn from_chrono(delta: &TimeDelta) -> Option<Self> {
+            from_secs::<U>(delta.num_seconds(), delta.subsec_nanos() as i64).map(Self::new)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn includes_unit_in_type_string() {
+        assert_eq!(DateTime64::<Nanoseconds>::dtype().descr(), "'<M8[ns]'");
+        assert_eq!(TimeDelta64::<Microseconds>::dtype().descr(), "'<m8[us]'");
+        assert_eq!(DateTime64::<Days>::dtype().n_bytes().unwrap(), 8);
+    }
+
+    #[test]
+    fn represents_nat_as_none() {
+        let nat = DateTime64::<Seconds>::read(&[0, 0, 0, 0, 0, 0, 0, 0x80]);
+        assert!(nat.is_nat());
+        assert_eq!(nat.get(), None);
+        assert_eq!(nat, DateTime64::from(None));
+        assert_eq!(format!("{:?}", nat), "DateTime64(NaT)");
+        assert_eq!(format!("{:?}", TimeDelta64::<Hours>::new(-3)), "TimeDelta64(-3[h])");
+    }
+
+    #[test]
+    fn recognizes_unsupported_units() {
+        assert_eq!(unsupported_time_unit("M8[ns]"), None);
+        assert_eq!(unsupported_time_unit("m8[W]"), None);
+        assert_eq!(unsupported_time_unit("M8[Y]"), Some("Y"));
+        assert_eq!(unsupported_time_unit("m8[10ms]"), Some("10ms"));
+        assert_eq!(unsupported_time_unit("M8[ps]"), Some("ps"));
+        assert_eq!(unsupported_time_unit("M8"), Some(""));
+        assert_eq!(unsupported_time_unit("f8"), None);
+    }
+
+    #[cfg(feature = "chrono")]
+    #[test]
+    fn converts_to_and_from_chrono() {
+        use chrono::{NaiveDate, TimeDelta};
+
+        let time = NaiveDate::from_ymd_opt(1969, 12, 31).unwrap().and_hms_micro_opt(23, 59, 59, 500_000).unwrap();
+        let value = DateTime64::<Milliseconds>::from_chrono(&time).unwrap();
+        assert_eq!(value.get(), Some(-500));
+        assert_eq!(value.to_chrono(), Some(time));
+        assert_eq!(DateTime64::<Seconds>::from_chrono(&time).unwrap().get(), Some(-1));
+        assert_eq!(DateTime64::<Days>::NAT.to_chrono(), None);
+        assert_eq!(DateTime64::<Weeks>::new(i64::MAX).to_chrono(), None);
+
+        let delta = TimeDelta::new(90, 250).unwrap();
+        assert_eq!(TimeDelta64::<Nanoseconds>::from_chrono(&delta).unwrap().get(), Some(90_000_000_250));
+        assert_eq!(TimeDelta64::<Minutes>::new(-2).to_chrono(), TimeDelta::try_seconds(-120));
+    }
+}
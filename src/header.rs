@@ -1,10 +1,14 @@
 
 use nom::IResult;
 use std::collections::HashMap;
-use std::io::Result;
+use std::convert::TryFrom;
+use std::io::{Error, ErrorKind, Read, Result};
+use byteorder::{ByteOrder, LittleEndian, WriteBytesExt};
+
+use type_str::{item_size, split_byte_order};
 
 /// Representation of a Numpy type
-#[derive(PartialEq, Eq, Debug)]
+#[derive(PartialEq, Eq, Debug, Clone)]
 pub enum DType {
     /// A simple array with only a single field
     Plain {
@@ -21,10 +25,32 @@ pub enum DType {
     },
 
     /// A structure record array
-    Record(Vec<Field>)
+    Record(Vec<Field>),
+
+    /// An array of records, such as the field `('points', [('x', '<f4'), ('y', '<f4')], (4,))`
+    RecordArray {
+        /// Type of each record, which is either a `Record` or an `OffsetRecord`
+        record: Box<DType>,
+
+        /// Shape of the array, with the same meaning as for `Plain` types
+        shape: Vec<u64>,
+    },
+
+    /// A record whose fields are stored at explicit offsets, possibly in a different order and
+    /// with gaps between them. Numpy describes such records by a dict, e.g. for aligned structs.
+    OffsetRecord {
+        /// Fields of each record, in their logical order
+        fields: Vec<Field>,
+
+        /// Offset of each field in bytes, in the same order as `fields`
+        offsets: Vec<u64>,
+
+        /// Size of each record in bytes, including the padding
+        itemsize: u64,
+    },
 }
 
-#[derive(PartialEq, Eq, Debug)]
+#[derive(PartialEq, Eq, Debug, Clone)]
 /// A field of a record dtype
 pub struct Field {
     /// The name of the field
@@ -41,10 +67,10 @@ impl DType {
         match *self {
             Record(ref fields) =>
                 fields.iter()
-                    .map(|&Field { ref name, ref dtype }|
+                    .map(|Field { name, dtype }|
                         match *dtype {
                             Plain { ref ty, ref shape } =>
-                                if shape.len() == 0 {
+                                if shape.is_empty() {
                                     format!("('{}', '{}'), ", name, ty)
                                 } else {
                                     let shape_str = shape.iter().fold(String::new(), |o,n| o + &format!("{},", n));
@@ -53,20 +79,94 @@ impl DType {
                             ref record@Record(_) => {
                                     format!("('{}', {}), ", name, record.descr())
                                 },
+                            RecordArray { ref record, ref shape } => {
+                                    let shape_str = shape.iter().fold(String::new(), |o,n| o + &format!("{},", n));
+                                    format!("('{}', {}, ({})), ", name, record.descr(), shape_str)
+                                },
+                            ref record@OffsetRecord { .. } => {
+                                    format!("('{}', {}), ", name, record.descr())
+                                },
                         }
                     )
                     .fold("[".to_string(), |o, n| o + &n) + "]",
+            RecordArray { ref record, ref shape } => {
+                let shape_str = shape.iter().fold(String::new(), |o,n| o + &format!("{},", n));
+                format!("({}, ({}))", record.descr(), shape_str)
+            },
+            OffsetRecord { ref fields, ref offsets, itemsize } => {
+                let names = fields.iter().map(|field| format!("'{}'", field.name)).collect::<Vec<_>>();
+                let formats = fields.iter().map(|field| field.dtype.format_descr()).collect::<Vec<_>>();
+                let offsets = offsets.iter().map(|offset| offset.to_string()).collect::<Vec<_>>();
+                format!("{{'names': [{}], 'formats': [{}], 'offsets': [{}], 'itemsize': {}}}",
+                        names.join(", "), formats.join(", "), offsets.join(", "), itemsize)
+            },
             Plain { ref ty, .. } => format!("'{}'", ty),
         }
     }
 
+    /// Description of the dtype as an item of `formats` of a dict description, which includes
+    /// the shape of arrays
+    fn format_descr(&self) -> String {
+        use DType::*;
+        let shape_str = |shape: &[u64]| shape.iter().fold(String::new(), |o, n| o + &format!("{},", n));
+        match *self {
+            Plain { ref ty, ref shape } if !shape.is_empty() => format!("('{}', ({}))", ty, shape_str(shape)),
+            _ => self.descr(),
+        }
+    }
+
     /// Create from description AST
+    ///
+    /// Record dtypes can be described either as a list of `(name, dtype[, shape])` tuples, or
+    /// as a dictionary with `names`, `formats` and optionally `offsets` and `itemsize`. In the
+    /// latter case, the record is an `OffsetRecord` if the offsets or the itemsize are given.
     pub fn from_descr(descr: Value) -> Result<Self> {
         use DType::*;
         match descr {
             Value::String(string) => Ok(Plain { ty: string, shape: vec![] }),
-            Value::List(ref list) => Ok(Record(convert_list_to_record_fields(list)?)),
-            _ => invalid_data("must be string or list")
+            Value::List(ref list) => match convert_list_to_record_array(list) {
+                Some(record_array) => record_array,
+                None => Ok(Record(convert_list_to_record_fields(list)?)),
+            },
+            Value::Map(ref map) => convert_dict_to_record(map),
+            _ => invalid_data("must be string, list or dict")
+        }
+    }
+
+    /// Number of bytes taken by a single value of this type, including any padding
+    pub fn n_bytes(&self) -> Result<usize> {
+        match *self {
+            DType::Plain { ref ty, ref shape } =>
+                checked_size(item_size(split_byte_order(ty).1)?.checked_mul(shape_size(shape)?)),
+            DType::Record(ref fields) => record_size(fields),
+            DType::RecordArray { ref record, ref shape } =>
+                checked_size(record.n_bytes()?.checked_mul(shape_size(shape)?)),
+            DType::OffsetRecord { itemsize, .. } => checked_size(usize::try_from(itemsize).ok()),
+        }
+    }
+}
+
+fn record_size(fields: &[Field]) -> Result<usize> {
+    fields.iter().try_fold(0, |size: usize, field| checked_size(size.checked_add(field.dtype.n_bytes()?)))
+}
+
+/// Number of items in an array of the given shape, or an error if it doesn't fit into `usize`
+pub fn shape_size(shape: &[u64]) -> Result<usize> {
+    let size = shape.iter().try_fold(1usize, |size, &n| usize::try_from(n).ok().and_then(|n| size.checked_mul(n)));
+    size.map_or_else(|| invalid_data("the shape is too large"), Ok)
+}
+
+fn checked_size(size: Option<usize>) -> Result<usize> {
+    size.map_or_else(|| invalid_data("the dtype is too large"), Ok)
+}
+
+impl Field {
+    /// Whether this is an unnamed void field used for padding
+    pub fn is_padding(&self) -> bool {
+        match self.dtype {
+            DType::Plain { ref ty, .. } =>
+                self.name.is_empty() && split_byte_order(ty).1.starts_with('V'),
+            DType::Record(_) | DType::RecordArray { .. } | DType::OffsetRecord { .. } => false,
         }
     }
 }
@@ -79,27 +179,132 @@ fn convert_list_to_record_fields(values: &[Value]) -> Result<Vec<Field>> {
         }))
 }
 
+fn convert_dict_to_record(dict: &HashMap<String, Value>) -> Result<DType> {
+    let names = match dict.get("names") {
+        Some(Value::List(names)) => first_error(names.iter().map(|name| match *name {
+            Value::String(ref name) => Ok(name.clone()),
+            _ => invalid_data("names must be strings"),
+        }))?,
+        _ => return invalid_data("dict must contain a list of names"),
+    };
+    let formats = match dict.get("formats") {
+        Some(Value::List(formats)) => first_error(formats.iter().map(convert_value_to_format))?,
+        _ => return invalid_data("dict must contain a list of formats"),
+    };
+    if names.len() != formats.len() {
+        return invalid_data("there must be as many formats as names");
+    }
+
+    let fields = names.into_iter().zip(formats).map(|(name, dtype)| Field { name, dtype }).collect::<Vec<_>>();
+
+    // Without offsets and itemsize, the fields are packed just like in the list form
+    if dict.get("offsets").is_none() && dict.get("itemsize").is_none() {
+        return Ok(DType::Record(fields));
+    }
+
+    let offsets = match dict.get("offsets") {
+        Some(Value::List(offsets)) if offsets.len() == fields.len() =>
+            first_error(offsets.iter().map(convert_value_to_offset))?,
+        Some(_) => return invalid_data("there must be an offset for each field"),
+        None => {
+            let mut offsets = vec![];
+            let mut end = 0u64;
+            for field in &fields {
+                offsets.push(end);
+                end = checked_end(end, &field.dtype)?;
+            }
+            offsets
+        },
+    };
+
+    // The fields may be stored in any order, but they mustn't overlap
+    let mut layout = offsets.iter().zip(&fields).collect::<Vec<_>>();
+    layout.sort_by_key(|&(&offset, _)| offset);
+    let mut end = 0;
+    for (&offset, field) in layout {
+        if offset < end {
+            return invalid_data("overlapping fields are not supported");
+        }
+        end = checked_end(offset, &field.dtype)?;
+    }
+
+    let itemsize = match dict.get("itemsize") {
+        Some(itemsize) => convert_value_to_offset(itemsize)?,
+        None => end,
+    };
+    if itemsize < end {
+        return invalid_data("itemsize is smaller than the fields");
+    }
+
+    Ok(DType::OffsetRecord { fields, offsets, itemsize })
+}
+
+/// The offset right after a field of `dtype` stored at `offset`
+fn checked_end(offset: u64, dtype: &DType) -> Result<u64> {
+    offset.checked_add(dtype.n_bytes()? as u64).map_or_else(|| invalid_data("the offsets are too large"), Ok)
+}
+
+fn convert_value_to_format(format: &Value) -> Result<DType> {
+    if let Value::List(ref tuple) = *format {
+        // A subarray, such as `('<f8', (2,))`
+        if let [Value::String(ref ty), ref shape] = tuple[..] {
+            return Ok(DType::Plain { ty: ty.clone(), shape: convert_value_to_shape(shape)? });
+        }
+    }
+    DType::from_descr(format.clone())
+}
+
+/// Convert a subarray of records, such as `([('x', '<f4')], (2,))` or `({'names': ...}, (2,))`.
+/// Returns `None` if the list doesn't have this form.
+fn convert_list_to_record_array(list: &[Value]) -> Option<Result<DType>> {
+    if let [ref record, Value::List(ref shape)] = list[..] {
+        let is_record = matches!(*record, Value::List(_) | Value::Map(_));
+        if is_record && shape.iter().all(|n| matches!(n, Value::Integer(_))) {
+            return Some(DType::from_descr(record.clone())
+                .and_then(|record| record_array(record, &list[1])));
+        }
+    }
+    None
+}
+
+/// A subarray of `record` with the given shape, or the record itself if the shape is empty
+fn record_array(record: DType, shape: &Value) -> Result<DType> {
+    let shape = convert_value_to_shape(shape)?;
+    if shape.is_empty() {
+        Ok(record)
+    } else {
+        Ok(DType::RecordArray { record: Box::new(record), shape })
+    }
+}
+
 fn convert_tuple_to_record_field(tuple: &[Value]) -> Result<Field> {
-    use self::Value::{String,List};
+    use self::Value::{String,List,Map};
 
     match tuple.len() {
         2 | 3 => match (&tuple[0], &tuple[1], tuple.get(2)) {
-            (&String(ref name), &String(ref dtype), ref shape) =>
+            (String(name), String(dtype), shape) =>
                 Ok(Field { name: name.clone(), dtype: DType::Plain {
                     ty: dtype.clone(),
-                    shape: if let &Some(ref s) = shape {
+                    shape: if let Some(s) = shape {
                         convert_value_to_shape(s)?
                     } else {
                         vec![]
                     }
                 } }),
-            (&String(ref name), &List(ref list), None) =>
+            (String(name), List(list), None) =>
                 Ok(Field {
                     name: name.clone(),
                     dtype: DType::Record(convert_list_to_record_fields(list)?)
                 }),
-            (&String(_), &List(_), Some(_)) =>
-                invalid_data("nested arrays of Record types are not supported."),
+            (String(name), List(list), Some(shape)) =>
+                Ok(Field {
+                    name: name.clone(),
+                    dtype: record_array(DType::Record(convert_list_to_record_fields(list)?), shape)?,
+                }),
+            (String(name), Map(map), None) =>
+                Ok(Field { name: name.clone(), dtype: convert_dict_to_record(map)? }),
+            (String(name), Map(map), Some(shape)) =>
+                Ok(Field { name: name.clone(), dtype: record_array(convert_dict_to_record(map)?, shape)? }),
             _ =>
                 invalid_data("list entry must contain a string for id and a valid dtype")
         },
@@ -109,24 +314,31 @@ fn convert_tuple_to_record_field(tuple: &[Value]) -> Result<Field> {
 
 fn convert_value_to_shape(field: &Value) -> Result<Vec<u64>> {
     if let Value::List(ref lengths) = *field {
-        first_error(lengths.iter().map(convert_value_to_positive_integer))
+        first_error(lengths.iter().map(convert_value_to_dimension))
     } else {
         invalid_data("shape must be list or tuple")
     }
 }
 
-fn convert_value_to_positive_integer(number: &Value) -> Result<u64> {
+fn convert_value_to_dimension(number: &Value) -> Result<u64> {
     if let Value::Integer(number) = *number {
-        if number > 0 {
+        if number >= 0 {
             Ok(number as u64)
         } else {
-            invalid_data("number must be positive")
+            invalid_data("number must be non-negative")
         }
     } else {
         invalid_data("must be a number")
     }
 }
 
+fn convert_value_to_offset(number: &Value) -> Result<u64> {
+    match *number {
+        Value::Integer(number) if number >= 0 => Ok(number as u64),
+        _ => invalid_data("offsets and sizes must be non-negative numbers"),
+    }
+}
+
 fn first_error<I, T>(results: I) -> Result<Vec<T>>
     where I: IntoIterator<Item=Result<T>>
 {
@@ -138,10 +350,13 @@ fn first_error<I, T>(results: I) -> Result<Vec<T>>
 }
 
 fn invalid_data<T>(message: &str) -> Result<T> {
-    use std::io::{Error, ErrorKind};
     Err(Error::new(ErrorKind::InvalidData, message.to_string()))
 }
 
+fn invalid_input<T>(message: &str) -> Result<T> {
+    Err(Error::new(ErrorKind::InvalidInput, message.to_string()))
+}
+
 #[derive(PartialEq, Eq, Debug, Clone)]
 pub enum Value {
     String(String),
@@ -151,25 +366,259 @@ pub enum Value {
     Map(HashMap<String,Value>),
 }
 
-pub fn parse_header(bs: &[u8]) -> IResult<&[u8], Value> {
-    parser::header(bs)
+/// Version of the NPY file format
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub enum Version {
+    /// Version 1.0, with a Latin-1 header of at most 65535 bytes
+    V1,
+    /// Version 2.0, which allows headers of up to 4 GiB
+    V2,
+    /// Version 3.0, which is like 2.0, but the header is encoded in UTF-8
+    V3,
+}
+
+impl Version {
+    /// Major and minor version number, as stored in the file
+    pub fn number(self) -> (u8, u8) {
+        match self {
+            Version::V1 => (1, 0),
+            Version::V2 => (2, 0),
+            Version::V3 => (3, 0),
+        }
+    }
+}
+
+/// Order in which the elements of a multidimensional array are stored
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub enum Order {
+    /// Row-major order, where the last index varies the fastest
+    C,
+    /// Column-major order, where the first index varies the fastest
+    Fortran,
+}
+
+/// The header of a NPY file, describing the data that follow it
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub struct Header {
+    /// The type of a single record
+    pub dtype: DType,
+
+    /// Whether a multidimensional array is stored in column-major order
+    pub fortran_order: bool,
+
+    /// Shape of the array. Scalars have zero entries.
+    pub shape: Vec<u64>,
+
+    /// Version of the file format
+    pub version: Version,
+}
+
+impl Header {
+    /// Create a header using the oldest format version able to represent it
+    ///
+    /// Fails if the header is too long to be stored in any format version.
+    pub fn new(dtype: DType, shape: Vec<u64>, fortran_order: bool) -> Result<Header> {
+        let shape_str = shape_to_string(&shape);
+        let (_, version, _) = encode_header(&dtype, fortran_order, shape_str.as_bytes(), None)?;
+        Ok(Header { dtype, fortran_order, shape, version })
+    }
+
+    /// Parse the header at the beginning of a NPY file.
+    ///
+    /// Returns the header along with the offset at which the data begin.
+    pub fn parse(bytes: &[u8]) -> Result<(Header, usize)> {
+        let (data, version, value) = parse_header(bytes)?;
+        Ok((Header::from_value(version, value)?, bytes.len() - data.len()))
+    }
+
+    /// Read the header from the beginning of a NPY file, leaving the reader positioned at the
+    /// start of the data.
+    pub fn from_reader<R: Read>(mut reader: R) -> Result<Header> {
+        let mut bytes = vec![0; 8];
+        reader.read_exact(&mut bytes)?;
+        let len_bytes = match (&bytes[..6], bytes[6]) {
+            (b"\x93NUMPY", 1) => 2,
+            (b"\x93NUMPY", 2) | (b"\x93NUMPY", 3) => 4,
+            (b"\x93NUMPY", _) => return invalid_data("unsupported format version"),
+            _ => return invalid_data("not a NPY file"),
+        };
+
+        bytes.resize(8 + len_bytes, 0);
+        reader.read_exact(&mut bytes[8..])?;
+        let len = if len_bytes == 2 {
+            LittleEndian::read_u16(&bytes[8..]) as usize
+        } else {
+            LittleEndian::read_u32(&bytes[8..]) as usize
+        };
+
+        // The length isn't trusted to allocate, in case the input is truncated
+        reader.by_ref().take(len as u64).read_to_end(&mut bytes)?;
+        if bytes.len() < 8 + len_bytes + len {
+            return Err(Error::new(ErrorKind::UnexpectedEof, "the header is shorter than its declared length"));
+        }
+        Header::parse(&bytes).map(|(header, _)| header)
+    }
+
+    /// Serialize the header, including the magic string and the version, so that it can be
+    /// directly followed by the data.
+    ///
+    /// Fails if the header can't be represented in the chosen format version.
+    pub fn to_bytes(&self) -> Result<Vec<u8>> {
+        let shape_str = shape_to_string(&self.shape);
+        encode_header(&self.dtype, self.fortran_order, shape_str.as_bytes(), Some(self.version))
+            .map(|(bytes, _, _)| bytes)
+    }
+
+    fn from_value(version: Version, header: Value) -> Result<Header> {
+        let map = match header {
+            Value::Map(map) => map,
+            _ => return invalid_data("header is not a dictionary"),
+        };
+
+        let dtype = match map.get("descr") {
+            Some(descr) => DType::from_descr(descr.clone())?,
+            None => return invalid_data("\'descr\' field is not present"),
+        };
+
+        let fortran_order = match map.get("fortran_order") {
+            Some(&Value::Bool(fortran_order)) => fortran_order,
+            _ => return invalid_data("\'fortran_order\' field is not present or doesn't contain a boolean."),
+        };
+
+        let shape = match map.get("shape") {
+            Some(shape) => convert_value_to_shape(shape)?,
+            None => return invalid_data("\'shape\' field is not present"),
+        };
+
+        Ok(Header { dtype, fortran_order, shape, version })
+    }
+}
+
+/// Format the shape as the contents of a Python tuple, with a trailing comma after each item
+pub fn shape_to_string(shape: &[u64]) -> String {
+    shape.iter().map(|n| format!("{},", n)).collect::<Vec<_>>().join(" ")
+}
+
+/// Create the magic string, version, header length and the padded header dictionary.
+///
+/// `shape` is the contents of the shape tuple. If `version` is `None`, the oldest format
+/// version able to represent the header is chosen: 1.0 by default, 2.0 if the header is
+/// longer than 65535 bytes, and 3.0 if it can't be encoded in Latin-1.
+///
+/// Returns the bytes together with the version and the position of the shape.
+pub fn encode_header(dtype: &DType, fortran_order: bool, shape: &[u8], version: Option<Version>)
+        -> Result<(Vec<u8>, Version, usize)> {
+    let fortran_order = if fortran_order { "True" } else { "False" };
+    let prefix = format!("{{'descr': {}, 'fortran_order': {}, 'shape': (",
+                         dtype.descr(), fortran_order);
+    let suffix = "), }";
+
+    let latin1 = prefix.chars().all(|c| (c as u32) < 256);
+    let latin1_len = prefix.chars().count();
+    let utf8_len = prefix.len();
+
+    // The header is terminated by a newline and padded to 16 bytes together with the preamble
+    let unpadded = |prefix_len: usize| prefix_len + shape.len() + suffix.len() + 1;
+    let padded = |prefix_len: usize, preamble_len: usize| {
+        let unpadded = unpadded(prefix_len);
+        unpadded + (16 - (preamble_len + unpadded) % 16) % 16
+    };
+    let fits_v1 = latin1 && padded(latin1_len, 10) <= u16::MAX as usize;
+    let fits_v2 = latin1 && padded(latin1_len, 12) <= u32::MAX as usize;
+    let fits_v3 = padded(utf8_len, 12) <= u32::MAX as usize;
+
+    let version = match version {
+        None if fits_v1 => Version::V1,
+        None if fits_v2 => Version::V2,
+        None if fits_v3 => Version::V3,
+        None => return invalid_input("the header is too long to be stored in any NPY format version"),
+        Some(Version::V1) if !fits_v1 => return invalid_input("the header can't be stored in NPY format version 1.0"),
+        Some(Version::V2) if !fits_v2 => return invalid_input("the header can't be stored in NPY format version 2.0"),
+        Some(Version::V3) if !fits_v3 => return invalid_input("the header can't be stored in NPY format version 3.0"),
+        Some(version) => version,
+    };
+
+    // Versions 1.0 and 2.0 store the header in Latin-1, version 3.0 in UTF-8
+    let prefix: Vec<u8> = match version {
+        Version::V1 | Version::V2 => prefix.chars().map(|c| c as u8).collect(),
+        Version::V3 => prefix.into_bytes(),
+    };
+    let unpadded = unpadded(prefix.len());
+
+    let mut header: Vec<u8> = vec![];
+    header.extend(&[0x93u8]);
+    header.extend(b"NUMPY");
+    let (major, minor) = version.number();
+    header.extend(&[major, minor]);
+    let len = if version == Version::V1 {
+        let len = padded(prefix.len(), 10);
+        header.write_u16::<LittleEndian>(len as u16)?;
+        len
+    } else {
+        let len = padded(prefix.len(), 12);
+        header.write_u32::<LittleEndian>(len as u32)?;
+        len
+    };
+    let start = header.len();
+
+    header.extend(&prefix);
+    let shape_pos = header.len();
+    header.extend(shape);
+    header.extend(suffix.as_bytes());
+    header.extend(&vec![b' '; len - unpadded]);
+    header.extend(b"\n");
+    assert_eq!(header.len() - start, len);
+    assert_eq!(header.len() % 16, 0);
+
+    Ok((header, version, shape_pos))
+}
+
+/// Parse the magic string, version and header of a NPY file.
+///
+/// Returns the remaining data along with the format version and the header dictionary.
+pub fn parse_header(bs: &[u8]) -> Result<(&[u8], Version, Value)> {
+    let (data, (version, raw)) = match parser::preamble(bs) {
+        IResult::Done(data, preamble) => (data, preamble),
+        IResult::Incomplete(needed) => return invalid_data(&format!("{:?}", needed)),
+        IResult::Error(err) => return invalid_data(&format!("{:?}", err)),
+    };
+
+    let text = match version {
+        Version::V1 | Version::V2 => raw.iter().map(|&b| b as char).collect(),
+        Version::V3 => match ::std::str::from_utf8(raw) {
+            Ok(text) => text.to_string(),
+            Err(_) => return invalid_data("header is not valid UTF-8"),
+        },
+    };
+
+    match parser::item(text.as_bytes()) {
+        IResult::Done(_, header) => Ok((data, version, header)),
+        IResult::Incomplete(needed) => invalid_data(&format!("{:?}", needed)),
+        IResult::Error(err) => invalid_data(&format!("{:?}", err)),
+    }
 }
 
 mod parser {
-    use super::Value;
+    use super::{Value, Version};
     use nom::*;
 
-    named!(pub header<Value>,
+    named!(pub preamble<(Version, &[u8])>,
         do_parse!(
             tag!(&[0x93u8]) >>
             tag!(b"NUMPY") >>
-            tag!(&[0x01u8, 0x00]) >>
-            hdr: length_value!(le_u16, item) >>
-            (hdr)
+            version: alt!(
+                tag!(&[0x01u8, 0x00]) => { |_| Version::V1 } |
+                tag!(&[0x02u8, 0x00]) => { |_| Version::V2 } |
+                tag!(&[0x03u8, 0x00]) => { |_| Version::V3 }
+            ) >>
+            hdr: switch!(value!(version),
+                Version::V1 => length_bytes!(le_u16) |
+                _ => length_bytes!(le_u32)
+            ) >>
+            ((version, hdr))
         )
     );
 
-
     named!(pub integer<Value>,
         map!(
             map_res!(
@@ -196,10 +645,10 @@ mod parser {
                 map_res!(
                     ws!(alt!(
                         delimited!(tag!("\""),
-                            is_not_s!("\""),
+                            take_until_s!("\""),
                             tag!("\"")) |
                         delimited!(tag!("\'"),
-                            is_not_s!("\'"),
+                            take_until_s!("\'"),
                             tag!("\'"))
                         )),
                     ::std::str::from_utf8
@@ -283,6 +732,18 @@ mod tests {
         assert_eq!(dtype.descr(), "[('parent', [('child', '<i4'), ]), ]");
     }
 
+    #[test]
+    fn description_of_record_subarray_field() {
+        let point = vec![
+            Field { name: "x".to_string(), dtype: DType::Plain { ty: "<f4".to_string(), shape: vec![] } },
+        ];
+        let dtype = DType::Record(vec![
+            Field { name: "points".to_string(), dtype: DType::RecordArray { record: Box::new(DType::Record(point)), shape: vec![2, 4] } },
+        ]);
+        assert_eq!(dtype.descr(), "[('points', [('x', '<f4'), ], (2,4,)), ]");
+        assert_eq!(dtype.n_bytes().unwrap(), 32);
+    }
+
     #[test]
     fn converts_simple_description_to_record_dtype() {
         let dtype = ">f8".to_string();
@@ -337,10 +798,149 @@ mod tests {
         assert_eq!(DType::from_descr(descr).unwrap(), expected_dtype);
     }
 
-
     #[test]
-    fn errors_on_nested_record_field_array() {
+    fn record_description_with_record_subarray_field() {
+        let expected_dtype = DType::Record(vec![
+            Field {
+                name: "parent".to_string(),
+                dtype: DType::RecordArray {
+                    record: Box::new(DType::Record(vec![Field {
+                        name: "child".to_string(),
+                        dtype: DType::Plain { ty: "<i4".to_string(), shape: vec![] }
+                    }])),
+                    shape: vec![2],
+                },
+            }
+        ]);
         let descr = parse("[('parent', [('child', '<i4')], (2,))]");
+        assert_eq!(DType::from_descr(descr).unwrap(), expected_dtype);
+        let descr = parse("{'names': ['parent'], 'formats': [([('child', '<i4')], (2,))]}");
+        assert_eq!(DType::from_descr(descr).unwrap(), expected_dtype);
+        assert_eq!(DType::from_descr(parse(&expected_dtype.descr())).unwrap(), expected_dtype);
+    }
+
+    #[test]
+    fn description_of_top_level_record_subarray() {
+        let dtype = DType::RecordArray {
+            record: Box::new(DType::Record(vec![Field {
+                name: "child".to_string(),
+                dtype: DType::Plain { ty: "<i4".to_string(), shape: vec![] }
+            }])),
+            shape: vec![2, 3],
+        };
+        assert_eq!(dtype.descr(), "([('child', '<i4'), ], (2,3,))");
+        assert_eq!(DType::from_descr(parse(&dtype.descr())).unwrap(), dtype);
+
+        let header = Header::new(dtype, vec![4], false).unwrap();
+        assert_eq!(Header::parse(&header.to_bytes().unwrap()).unwrap().0, header);
+    }
+
+    #[test]
+    fn converts_record_subarray_with_empty_shape_to_record() {
+        let record = DType::Record(vec![Field {
+            name: "child".to_string(),
+            dtype: DType::Plain { ty: "<i4".to_string(), shape: vec![] }
+        }]);
+        assert_eq!(DType::from_descr(parse("([('child', '<i4')], ())")).unwrap(), record);
+        let descr = parse("[('parent', [('child', '<i4')], ())]");
+        assert_eq!(DType::from_descr(descr).unwrap(),
+                   DType::Record(vec![Field { name: "parent".to_string(), dtype: record }]));
+    }
+
+    #[test]
+    fn converts_dict_description_with_offsets_and_itemsize() {
+        let descr = parse("{'names': ['b', 'a'], 'formats': ['<f8', '|u1'], 'offsets': [8, 0], 'itemsize': 24}");
+        let expected_dtype = DType::OffsetRecord {
+            fields: vec![
+                Field { name: "b".to_string(), dtype: DType::Plain { ty: "<f8".to_string(), shape: vec![] } },
+                Field { name: "a".to_string(), dtype: DType::Plain { ty: "|u1".to_string(), shape: vec![] } },
+            ],
+            offsets: vec![8, 0],
+            itemsize: 24,
+        };
+        let dtype = DType::from_descr(descr).unwrap();
+        assert_eq!(dtype, expected_dtype);
+        assert_eq!(dtype.n_bytes().unwrap(), 24);
+        assert_eq!(dtype.descr(),
+                   "{'names': ['b', 'a'], 'formats': ['<f8', '|u1'], 'offsets': [8, 0], 'itemsize': 24}");
+        assert_eq!(DType::from_descr(parse(&dtype.descr())).unwrap(), dtype);
+    }
+
+    #[test]
+    fn converts_nested_dict_descriptions_back_and_forth() {
+        let descr = "[('pos', {'names': ['y', 'x'], 'formats': [('<f4', (2,)), [('z', '<i2')]], \
+                     'offsets': [4, 0], 'itemsize': 12}), ('id', '<u4')]";
+        let dtype = DType::from_descr(parse(descr)).unwrap();
+        assert_eq!(dtype.n_bytes().unwrap(), 16);
+        assert_eq!(DType::from_descr(parse(&dtype.descr())).unwrap(), dtype);
+    }
+
+    #[test]
+    fn converts_subarrays_of_dict_described_records() {
+        let point = "{'names': ['y', 'x'], 'formats': ['<f4', '|u1'], 'offsets': [4, 0], 'itemsize': 8}";
+        let descr = format!("[('p', {}, (2,))]", point);
+        let dtype = DType::from_descr(parse(&descr)).unwrap();
+        match dtype {
+            DType::Record(ref fields) => match fields[0].dtype {
+                DType::RecordArray { ref record, ref shape } => {
+                    assert!(matches!(**record, DType::OffsetRecord { itemsize: 8, .. }));
+                    assert_eq!(shape, &[2]);
+                },
+                ref dtype => panic!("unexpected dtype {:?}", dtype),
+            },
+            ref dtype => panic!("unexpected dtype {:?}", dtype),
+        }
+        assert_eq!(dtype.n_bytes().unwrap(), 16);
+        assert_eq!(DType::from_descr(parse(&dtype.descr())).unwrap(), dtype);
+
+        let descr = format!("{{'names': ['p'], 'formats': [({}, (2,))]}}", point);
+        assert_eq!(DType::from_descr(parse(&descr)).unwrap(), dtype);
+    }
+
+    #[test]
+    fn computes_dict_offsets_from_itemsize_alone() {
+        let descr = parse("{'names': ['a', 'b'], 'formats': ['|u1', '<i4'], 'itemsize': 8}");
+        match DType::from_descr(descr).unwrap() {
+            DType::OffsetRecord { offsets, itemsize, .. } => assert_eq!((offsets, itemsize), (vec![0, 1], 8)),
+            dtype => panic!("unexpected dtype {:?}", dtype),
+        }
+    }
+
+    #[test]
+    fn converts_dict_description_without_offsets() {
+        let descr = parse("{'names': ['a', 'b'], 'formats': ['<i4', ('<f4', (2,))]}");
+        let expected_dtype = DType::Record(vec![
+            Field { name: "a".to_string(), dtype: DType::Plain { ty: "<i4".to_string(), shape: vec![] } },
+            Field { name: "b".to_string(), dtype: DType::Plain { ty: "<f4".to_string(), shape: vec![2] } },
+        ]);
+        assert_eq!(DType::from_descr(descr).unwrap(), expected_dtype);
+    }
+
+    #[test]
+    fn converts_list_description_with_padding() {
+        let descr = parse("[('a', '|u1'), ('', '|V3'), ('b', '<i4')]");
+        let dtype = DType::from_descr(descr).unwrap();
+        if let DType::Record(ref fields) = dtype {
+            assert!(fields[1].is_padding());
+        }
+        assert_eq!(dtype.n_bytes().unwrap(), 8);
+    }
+
+    #[test]
+    fn errors_on_overlapping_dict_fields() {
+        let descr = parse("{'names': ['a', 'b'], 'formats': ['<i4', '<i4'], 'offsets': [0, 2]}");
+        assert!(DType::from_descr(descr).is_err());
+    }
+
+    #[test]
+    fn errors_on_too_small_itemsize() {
+        let descr = parse("{'names': ['a'], 'formats': ['<i4'], 'itemsize': 2}");
+        assert!(DType::from_descr(descr).is_err());
+    }
+
+    #[test]
+    fn errors_on_invalid_shape_of_nested_record_field_array() {
+        let descr = parse("[('parent', [('child', '<i4')], ('2',))]");
         assert!(DType::from_descr(descr).is_err());
     }
 
@@ -383,12 +983,149 @@ mod tests {
     #[test]
     fn errors_when_shape_number_is_not_a_number() {
         let no_number = parse("[]");
-        assert!(convert_value_to_positive_integer(&no_number).is_err());
+        assert!(convert_value_to_dimension(&no_number).is_err());
+    }
+
+    #[test]
+    fn errors_when_shape_number_is_negative() {
+        assert!(convert_value_to_dimension(&Value::Integer(-1)).is_err());
+        assert_eq!(convert_value_to_dimension(&parse("0")).unwrap(), 0);
+    }
+
+    #[test]
+    fn errors_when_size_overflows() {
+        let plain = DType::Plain { ty: "<f8".to_string(), shape: vec![1 << 62, 8] };
+        assert!(plain.n_bytes().is_err());
+        let point = vec![Field { name: "x".to_string(), dtype: DType::Plain { ty: "<f8".to_string(), shape: vec![] } }];
+        let record_array = DType::RecordArray { record: Box::new(DType::Record(point)), shape: vec![1 << 61] };
+        assert!(record_array.n_bytes().is_err());
+        assert!(shape_size(&[u64::MAX, 2]).is_err());
+        assert_eq!(shape_size(&[]).unwrap(), 1);
+    }
+
+    #[test]
+    fn parses_header_of_every_format_version() {
+        let dict = "{'descr': '<f8', 'fortran_order': False, 'shape': (3,), }";
+        for &(version, major) in &[(Version::V1, 1), (Version::V2, 2), (Version::V3, 3)] {
+            let bytes = npy_bytes(major, dict.as_bytes());
+            let (data, parsed_version, header) = parse_header(&bytes).unwrap();
+            assert_eq!(parsed_version, version);
+            assert_eq!(header, parse(dict));
+            assert_eq!(data, &[1, 2, 3]);
+        }
+    }
+
+    #[test]
+    fn decodes_header_as_latin1_or_utf8_depending_on_version() {
+        let latin1 = npy_bytes(1, b"{'descr': [('\xe9', '<f8')], }");
+        let utf8 = npy_bytes(3, "{'descr': [('\u{e9}', '<f8')], }".as_bytes());
+        let expected = parse("{'descr': [('\u{e9}', '<f8')], }");
+        assert_eq!(parse_header(&latin1).unwrap().2, expected);
+        assert_eq!(parse_header(&utf8).unwrap().2, expected);
+    }
+
+    #[test]
+    fn errors_on_unknown_format_version() {
+        assert!(parse_header(&npy_bytes(4, b"{}")).is_err());
     }
 
     #[test]
-    fn errors_when_shape_number_is_not_positive() {
-        assert!(convert_value_to_positive_integer(&parse("0")).is_err());
+    fn errors_on_invalid_utf8_in_version_3_header() {
+        assert!(parse_header(&npy_bytes(3, b"{'descr': [('\xe9', '<f8')], }")).is_err());
+    }
+
+    #[test]
+    fn parses_typed_header_and_data_offset() {
+        let bytes = npy_bytes(2, b"{'descr': '<f8', 'fortran_order': True, 'shape': (3, 4), }");
+        let (header, offset) = Header::parse(&bytes).unwrap();
+        assert_eq!(header, Header {
+            dtype: DType::Plain { ty: "<f8".to_string(), shape: vec![] },
+            fortran_order: true,
+            shape: vec![3, 4],
+            version: Version::V2,
+        });
+        assert_eq!(&bytes[offset..], &[1, 2, 3]);
+    }
+
+    #[test]
+    fn reads_header_and_leaves_reader_at_data() {
+        let bytes = npy_bytes(1, b"{'descr': '<f8', 'fortran_order': False, 'shape': (3,), }");
+        let mut reader = &bytes[..];
+        let header = Header::from_reader(&mut reader).unwrap();
+        assert_eq!(header.shape, vec![3]);
+        assert_eq!(reader, &[1, 2, 3]);
+    }
+
+    #[test]
+    fn errors_when_header_is_shorter_than_its_length() {
+        let bytes = b"\x93NUMPY\x02\x00\xff\xff\xff\xf0{'";
+        let err = Header::from_reader(&bytes[..]).err().unwrap();
+        assert_eq!(err.kind(), ErrorKind::UnexpectedEof);
+    }
+
+    #[test]
+    fn serialized_header_can_be_parsed_back() {
+        let header = Header::new(
+            DType::Record(vec![Field {
+                name: "a".to_string(),
+                dtype: DType::Plain { ty: "<i4".to_string(), shape: vec![2] }
+            }]),
+            vec![5, 0, 1],
+            true,
+        ).unwrap();
+        assert_eq!(header.version, Version::V1);
+        let bytes = header.to_bytes().unwrap();
+        assert_eq!(bytes.len() % 16, 0);
+        assert_eq!(Header::parse(&bytes).unwrap(), (header, bytes.len()));
+    }
+
+    #[test]
+    fn chooses_utf8_version_for_non_latin1_headers() {
+        let dtype = DType::Record(vec![Field {
+            name: "\u{3b1}".to_string(),
+            dtype: DType::Plain { ty: "<i4".to_string(), shape: vec![] }
+        }]);
+        let mut header = Header::new(dtype, vec![1], false).unwrap();
+        assert_eq!(header.version, Version::V3);
+        assert_eq!(Header::parse(&header.to_bytes().unwrap()).unwrap().0, header);
+
+        header.version = Version::V1;
+        assert!(header.to_bytes().is_err());
+    }
+
+    #[test]
+    fn encodes_explicit_version_3_headers_in_utf8() {
+        let dtype = DType::Record(vec![Field {
+            name: "\u{e9}".to_string(),
+            dtype: DType::Plain { ty: "<i4".to_string(), shape: vec![] }
+        }]);
+        let mut header = Header::new(dtype, vec![1], false).unwrap();
+        assert_eq!(header.version, Version::V1);
+        assert_eq!(Header::parse(&header.to_bytes().unwrap()).unwrap().0, header);
+
+        header.version = Version::V3;
+        assert_eq!(Header::parse(&header.to_bytes().unwrap()).unwrap().0, header);
+    }
+
+    #[test]
+    fn errors_on_header_without_required_keys() {
+        assert!(Header::parse(&npy_bytes(1, b"{'descr': '<f8', 'shape': (3,), }")).is_err());
+        assert!(Header::parse(&npy_bytes(1, b"{'descr': '<f8', 'fortran_order': False, }")).is_err());
+        assert!(Header::parse(&npy_bytes(1, b"{'fortran_order': False, 'shape': (3,), }")).is_err());
+    }
+
+    fn npy_bytes(major: u8, header: &[u8]) -> Vec<u8> {
+        let mut bytes = vec![0x93u8];
+        bytes.extend(b"NUMPY");
+        bytes.extend(&[major, 0x00]);
+        if major == 1 {
+            bytes.extend(&[header.len() as u8, 0]);
+        } else {
+            bytes.extend(&[header.len() as u8, 0, 0, 0]);
+        }
+        bytes.extend(header);
+        bytes.extend(&[1, 2, 3]);
+        bytes
     }
 
     fn parse(source: &str) -> Value {
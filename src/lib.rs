@@ -11,17 +11,31 @@ It stores the type, shape and endianness information in a header,
 which is followed by a flat binary data field. This crate offers a simple, mostly type-safe way to
 read and write *.npy files. Files are handled using iterators, so they don't need to fit in memory.
 
-One-dimensional arrays of types that implement the [`Serializable`](trait.Serializable.html) trait
-are supported. These are:
-
- * primitive types: `i8`, `u8`, `i16`, `u16`, `i32`, `u32`, `f32`, `f64`. These map to the `numpy`
-   types of `int8`, `uint8`, `int16`, etc.
+Arrays of any shape are supported, as long as their records are of types that implement the
+[`Serializable`](trait.Serializable.html) trait. These are:
+
+ * primitive types: `i8`, `u8`, `i16`, `u16`, `i32`, `u32`, `f32`, `f64`, `bool`. These map to the
+   `numpy` types of `int8`, `uint8`, `int16`, etc.
+ * complex numbers: [`Complex<f32>`](struct.Complex.html) and `Complex<f64>`, mapping to
+   `complex64` and `complex128`. With the `num-complex` feature, `num_complex::Complex` is
+   supported as well.
+ * half-precision floats: `half::f16`, mapping to `float16`, with the `half` feature. The `bf16`
+   feature additionally supports `half::bf16`, stored as `uint16`, since `numpy` has no
+   `bfloat16` type.
+ * fixed-length strings: [`FixedBytes<N>`](struct.FixedBytes.html) and
+   [`FixedStr<N>`](struct.FixedStr.html), mapping to `numpy`'s `SN` and `UN` types.
+ * opaque bytes: [`Void<N>`](struct.Void.html), mapping to `numpy`'s `VN` type.
+ * dates and times: [`DateTime64<U>`](struct.DateTime64.html) and
+   [`TimeDelta64<U>`](struct.TimeDelta64.html), mapping to `datetime64` and `timedelta64` with
+   the unit `U`. With the `chrono` feature, they convert to and from `chrono` types.
+ * tuples of up to 12 `Serializable` types. These map to structured arrays with `numpy`'s default
+   field names `f0`, `f1`, etc.
  * `struct`s annotated as `#[derive(Serializable)]`. These map to `numpy`'s
-     [Structured arrays](https://docs.scipy.org/doc/numpy/user/basics.rec.html). They can contain the
-     following field types:
+   [Structured arrays](https://docs.scipy.org/doc/numpy/user/basics.rec.html). They can contain the
+   following field types:
    * primitive types,
    * other [`Serializable`](trait.Serializable.html) structs,
-   * arrays of [`Serializable`](trait.Serializable.html) types (including arrays) of length ≤ 16.
+   * arrays of [`Serializable`](trait.Serializable.html) types (including arrays) of any length.
  * `struct`s with manual [`Serializable`](trait.Serializable.html) implementations. An example
    this can be found in the [roundtrip test](https://github.com/potocpav/npy-rs/tree/master/tests/roundtrip.rs).
 
@@ -31,7 +45,16 @@ struct must contain:
 * corresponding number of fields in the same order,
 * corresponding names of fields,
 * compatible field types.
-* only little endian fields
+
+Alternatively, [`NpyData::from_bytes_by_name`](struct.NpyData.html#method.from_bytes_by_name)
+matches the fields by their names only. Fields which are not in the struct are then skipped, and
+fields marked as `#[npy(default)]` may be missing in the file.
+
+Fields may be stored in either byte order; the bytes are swapped on read if necessary.
+
+Arrays of Python objects (`dtype=object`) are stored as pickles, so they can't be read as
+`NpyData`. Arrays of strings, numbers and `None` can be read using
+[`ObjectArray`](struct.ObjectArray.html) instead.
 
 # Examples
 
@@ -125,15 +148,36 @@ Array { a: 2, b: 3.1, c: 5 }
 */
 
 extern crate byteorder;
-#[macro_use]
 extern crate nom;
+#[cfg(feature = "num-complex")]
+extern crate num_complex;
+#[cfg(feature = "half")]
+extern crate half;
+#[cfg(feature = "chrono")]
+extern crate chrono;
 
 mod header;
 mod serializable;
 mod npy_data;
 mod out_file;
+mod complex;
+mod convert;
+mod type_str;
+mod strings;
+mod datetime;
+mod void;
+mod pickle;
+mod object_array;
 
 pub use serializable::Serializable;
-pub use header::{DType, Field};
+pub use convert::FieldDefaults;
+pub use complex::Complex;
+pub use strings::{FixedBytes, FixedStr};
+pub use void::Void;
+pub use datetime::{DateTime64, TimeDelta64, TimeUnit};
+pub use datetime::{Weeks, Days, Hours, Minutes, Seconds, Milliseconds, Microseconds, Nanoseconds};
+pub use header::{DType, Field, Header, Order, Version};
 pub use npy_data::NpyData;
+pub use object_array::ObjectArray;
+pub use pickle::PyObject;
 pub use out_file::{to_file, OutFile};
@@ -1,11 +1,14 @@
 
-use nom::*;
 use std::io::{Result, ErrorKind, Error};
 use std::marker::PhantomData;
 
-use header::{Value, DType, parse_header};
+use header::{shape_size, Header, Order, Version};
+use convert::Conversion;
+use object_array::is_object_dtype;
 use serializable::Serializable;
 
+/// Records converted by [`NpyData::get_unchecked`] up to this size don't allocate
+const STACK_BUFFER_SIZE: usize = 256;
 
 /// The data structure representing a deserialized `npy` file.
 ///
@@ -13,21 +16,86 @@ use serializable::Serializable;
 /// as a byte array, and deserialized only on-demand to minimize unnecessary allocations.
 /// The whole contents of the file can be deserialized by the [`to_vec`](#method.to_vec)
 /// member function.
+///
+/// Flat access by [`get`](#method.get), [`to_vec`](#method.to_vec) and iteration returns
+/// records in the order they are stored in, which is given by [`order`](#method.order).
+/// Multidimensional arrays can be accessed in their logical order using
+/// [`get_nd`](#method.get_nd) and [`to_vec_c_order`](#method.to_vec_c_order).
 pub struct NpyData<'a, T> {
     data: &'a [u8],
+    shape: Vec<u64>,
+    order: Order,
     n_records: usize,
+    version: Version,
+    conversion: Option<Conversion>,
     _t: PhantomData<T>,
 }
 
 impl<'a, T: Serializable> NpyData<'a, T> {
     /// Deserialize a NPY file represented as bytes
     pub fn from_bytes(bytes: &'a [u8]) -> ::std::io::Result<NpyData<'a, T>> {
-        let (data_slice, ns) = Self::get_data_slice(bytes)?;
-        Ok(NpyData { data: data_slice, n_records: ns as usize, _t: PhantomData })
+        Self::from_bytes_(bytes, false)
+    }
+
+    /// Deserialize a NPY file represented as bytes, matching the fields of records by their
+    /// names
+    ///
+    /// Unlike [`from_bytes`](#method.from_bytes), the fields in the file may be in any order,
+    /// and the ones which `T` doesn't contain are skipped. Fields of `T` missing in the file are
+    /// filled in from [`Serializable::field_defaults`](trait.Serializable.html#method.field_defaults),
+    /// i.e. the fields marked as `#[npy(default)]` when derived. It's an error if they have no
+    /// default.
+    pub fn from_bytes_by_name(bytes: &'a [u8]) -> ::std::io::Result<NpyData<'a, T>> {
+        Self::from_bytes_(bytes, true)
     }
 
-    /// Gets a single data-record with the specified index. Returns None, if the index is
-    /// out of bounds
+    fn from_bytes_(bytes: &'a [u8], by_name: bool) -> ::std::io::Result<NpyData<'a, T>> {
+        let (data_slice, header, conversion) = Self::get_data_slice(bytes, by_name)?;
+        let Header { shape, fortran_order, version, .. } = header;
+        let order = if fortran_order { Order::Fortran } else { Order::C };
+        let n_records = shape_size(&shape)?;
+        let record_size = conversion.as_ref().map_or(T::n_bytes(), |c| c.src_size());
+        match n_records.checked_mul(record_size) {
+            Some(n_bytes) if n_bytes <= data_slice.len() => {},
+            _ => return Err(Error::new(ErrorKind::InvalidData,
+                format!("expected {} records, but the data is too short", n_records))),
+        }
+        Ok(NpyData { data: data_slice, shape, order, n_records, version, conversion, _t: PhantomData })
+    }
+
+    /// Deserialize a NPY file represented as bytes, checking that all the records are valid
+    ///
+    /// Unlike [`from_bytes`](#method.from_bytes), which reads invalid values leniently (e.g.
+    /// any non-zero byte as a `true` bool), this fails if
+    /// [`Serializable::validate`](trait.Serializable.html#method.validate) rejects any record.
+    pub fn from_bytes_strict(bytes: &'a [u8]) -> ::std::io::Result<NpyData<'a, T>> {
+        let data = Self::from_bytes(bytes)?;
+        let mut buf = vec![];
+        for i in 0..data.n_records {
+            if let Err(err) = T::validate(data.record_bytes(i, &mut buf)) {
+                return Err(Error::new(ErrorKind::InvalidData, format!("record {}: {}", i, err)));
+            }
+        }
+        Ok(data)
+    }
+
+    /// Returns the shape of the array
+    pub fn shape(&self) -> &[u64] {
+        &self.shape
+    }
+
+    /// Returns the order in which the records are stored
+    pub fn order(&self) -> Order {
+        self.order
+    }
+
+    /// Returns the number of dimensions of the array
+    pub fn ndim(&self) -> usize {
+        self.shape.len()
+    }
+
+    /// Gets a single data-record with the specified index in storage order. Returns None, if
+    /// the index is out of bounds
     pub fn get(&self, i: usize) -> Option<T> {
         if i < self.n_records {
             Some(self.get_unchecked(i))
@@ -36,76 +104,136 @@ impl<'a, T: Serializable> NpyData<'a, T> {
         }
     }
 
+    /// Gets a single data-record at the specified multi-dimensional index. Returns None, if
+    /// the number of coordinates doesn't match [`ndim`](#method.ndim) or the index is out of
+    /// bounds.
+    pub fn get_nd(&self, index: &[u64]) -> Option<T> {
+        if index.len() != self.shape.len() {
+            return None;
+        }
+        if index.iter().zip(&self.shape).any(|(&i, &n)| i >= n) {
+            return None;
+        }
+        let mut flat = 0;
+        match self.order {
+            Order::C => for (&i, &n) in index.iter().zip(&self.shape) {
+                flat = flat * n + i;
+            },
+            Order::Fortran => for (&i, &n) in index.iter().zip(&self.shape).rev() {
+                flat = flat * n + i;
+            },
+        }
+        Some(self.get_unchecked(flat as usize))
+    }
+
     /// Returns the total number of records
     pub fn len(&self) -> usize {
         self.n_records
     }
 
+    /// Returns the version of the NPY format the data was stored in
+    pub fn version(&self) -> Version {
+        self.version
+    }
+
     /// Returns whether there are zero records in this NpyData structure
     pub fn is_empty(&self) -> bool {
         self.n_records == 0
     }
 
-    /// Gets a single data-record wit the specified index. Panics, if the index is out of bounds.
+    /// Gets a single data-record with the specified index in storage order. Panics, if the index
+    /// is out of bounds.
     pub fn get_unchecked(&self, i: usize) -> T {
-        T::read(&self.data[i * T::n_bytes()..])
+        match self.conversion {
+            // Single records are usually small enough to be converted on the stack
+            Some(ref conversion) if conversion.dst_size() <= STACK_BUFFER_SIZE => {
+                let mut buf = [0; STACK_BUFFER_SIZE];
+                conversion.apply(&self.data[i * conversion.src_size()..], &mut buf);
+                T::read(&buf)
+            },
+            _ => T::read(self.record_bytes(i, &mut vec![])),
+        }
+    }
+
+    /// The bytes of a record in the layout expected by `T`. If the record has to be converted,
+    /// it is converted into `buf`, which can be reused to avoid an allocation for every record.
+    fn record_bytes<'b>(&'b self, i: usize, buf: &'b mut Vec<u8>) -> &'b [u8] {
+        match self.conversion {
+            None => &self.data[i * T::n_bytes()..],
+            Some(ref conversion) => {
+                buf.resize(conversion.dst_size(), 0);
+                conversion.apply(&self.data[i * conversion.src_size()..], buf);
+                buf
+            },
+        }
     }
 
-    /// Construct a vector with the deserialized contents of the whole file
+    /// Construct a vector with the deserialized contents of the whole file in storage order
     pub fn to_vec(&self) -> Vec<T> {
         let mut v = Vec::with_capacity(self.n_records);
+        let mut buf = vec![];
         for i in 0..self.n_records {
-            v.push(self.get_unchecked(i));
+            v.push(T::read(self.record_bytes(i, &mut buf)));
         }
         v
     }
 
-    fn get_data_slice(bytes: &[u8]) -> Result<(&[u8], i64)> {
-        let (data, header) = match parse_header(bytes) {
-            IResult::Done(data, header) => {
-                Ok((data, header))
-            },
-            IResult::Incomplete(needed) => {
-                Err(Error::new(ErrorKind::InvalidData, format!("{:?}", needed)))
-            },
-            IResult::Error(err) => {
-                Err(Error::new(ErrorKind::InvalidData, format!("{:?}", err)))
+    /// Construct a vector with the deserialized contents of the whole file in C (row-major)
+    /// order, regardless of the order they are stored in
+    pub fn to_vec_c_order(&self) -> Vec<T> {
+        if self.order == Order::C {
+            return self.to_vec();
+        }
+        let mut v = Vec::with_capacity(self.n_records);
+        let mut buf = vec![];
+        let mut index = vec![0; self.shape.len()];
+        for _ in 0..self.n_records {
+            // The first index varies the fastest in storage, so it has the smallest stride
+            let mut flat = 0;
+            for (&i, &n) in index.iter().zip(&self.shape).rev() {
+                flat = flat * n + i;
             }
-        }?;
-
-
-        let ns: i64 =
-            if let Value::Map(ref map) = header {
-                if let Some(&Value::List(ref l)) = map.get("shape") {
-                    if l.len() == 1 {
-                        if let Some(&Value::Integer(ref n)) = l.get(0) {
-                            Some(*n)
-                        } else { None }
-                    } else { None }
-                } else { None }
-            } else { None }
-            .ok_or_else(|| Error::new(ErrorKind::InvalidData,
-                    "\'shape\' field is not present or doesn't consist of a tuple of length 1."))?;
-
-        let descr: &Value =
-            if let Value::Map(ref map) = header {
-                map.get("descr")
-            } else { None }
-            .ok_or_else(|| Error::new(ErrorKind::InvalidData,
-                    "\'descr\' field is not present or doesn't contain a list."))?;
-
-        if let Ok(dtype) = DType::from_descr(descr.clone()) {
-            let expected_dtype = T::dtype();
-            if dtype != expected_dtype {
-                return Err(Error::new(ErrorKind::InvalidData,
-                    format!("Types don't match! found: {:?}, expected: {:?}", dtype, expected_dtype)
-                ));
+            v.push(T::read(self.record_bytes(flat as usize, &mut buf)));
+
+            for (i, &n) in index.iter_mut().zip(&self.shape).rev() {
+                *i += 1;
+                if *i < n {
+                    break;
+                }
+                *i = 0;
             }
-        } else {
-            return Err(Error::new(ErrorKind::InvalidData, format!("fail?!?")));
+        }
+        v
+    }
+
+    fn get_data_slice(bytes: &[u8], by_name: bool) -> Result<(&[u8], Header, Option<Conversion>)> {
+        let (header, offset) = Header::parse(bytes)?;
+        if is_object_dtype(&header.dtype) {
+            return Err(Error::new(ErrorKind::InvalidData,
+                "the file contains pickled Python objects, which can be read by ObjectArray"));
         }
 
-        Ok((data, ns))
+        // Data which are not stored exactly as expected are converted on the fly
+        let expected_dtype = T::dtype();
+        let conversion = if header.dtype == expected_dtype {
+            None
+        } else {
+            let conversion = if by_name {
+                Conversion::by_name(&header.dtype, &expected_dtype, &T::field_defaults())
+            } else {
+                Conversion::new(&header.dtype, &expected_dtype)
+            };
+            match conversion {
+                Ok(ref conversion) if conversion.is_identity() => None,
+                Ok(conversion) => Some(conversion),
+                Err(err) => return Err(Error::new(ErrorKind::InvalidData,
+                    format!("Types don't match! found: {:?}, expected: {:?}: {}",
+                            header.dtype, expected_dtype, err)
+                )),
+            }
+        };
+
+        Ok((&bytes[offset..], header, conversion))
     }
 }
 
@@ -115,11 +243,13 @@ impl<'a, T: Serializable> NpyData<'a, T> {
 pub struct IntoIter<'a, T: 'a> {
     data: NpyData<'a, T>,
     i: usize,
+    /// Reused for converting the records
+    buf: Vec<u8>,
 }
 
 impl<'a, T> IntoIter<'a, T> {
     fn new(data: NpyData<'a, T>) -> Self {
-        IntoIter { data, i: 0 }
+        IntoIter { data, i: 0, buf: vec![] }
     }
 }
 
@@ -136,8 +266,11 @@ impl<'a, T> Iterator for IntoIter<'a, T> where T: Serializable {
     type Item = T;
 
     fn next(&mut self) -> Option<Self::Item> {
+        if self.i >= self.data.len() {
+            return None;
+        }
         self.i += 1;
-        self.data.get(self.i - 1)
+        Some(T::read(self.data.record_bytes(self.i - 1, &mut self.buf)))
     }
 
     fn size_hint(&self) -> (usize, Option<usize>) {
@@ -146,3 +279,94 @@ impl<'a, T> Iterator for IntoIter<'a, T> where T: Serializable {
 }
 
 impl<'a, T> ExactSizeIterator for IntoIter<'a, T> where T: Serializable {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_multidimensional_array() {
+        let bytes = npy_bytes("{'descr': '<u2', 'fortran_order': False, 'shape': (2, 3), }",
+                              &[0, 1, 2, 10, 11, 12]);
+        let data = NpyData::<u16>::from_bytes(&bytes).unwrap();
+        assert_eq!(data.shape(), &[2, 3]);
+        assert_eq!(data.ndim(), 2);
+        assert_eq!(data.len(), 6);
+        assert_eq!(data.get_nd(&[0, 2]), Some(2));
+        assert_eq!(data.get_nd(&[1, 0]), Some(10));
+        assert_eq!(data.get_nd(&[2, 0]), None);
+        assert_eq!(data.get_nd(&[1]), None);
+        assert_eq!(data.to_vec(), vec![0, 1, 2, 10, 11, 12]);
+    }
+
+    #[test]
+    fn reads_scalar_and_empty_arrays() {
+        let scalar = npy_bytes("{'descr': '<u2', 'fortran_order': False, 'shape': (), }", &[7]);
+        let data = NpyData::<u16>::from_bytes(&scalar).unwrap();
+        assert_eq!(data.ndim(), 0);
+        assert_eq!(data.get_nd(&[]), Some(7));
+        assert_eq!(data.to_vec(), vec![7]);
+
+        let empty = npy_bytes("{'descr': '<u2', 'fortran_order': False, 'shape': (3, 0), }", &[]);
+        let data = NpyData::<u16>::from_bytes(&empty).unwrap();
+        assert!(data.is_empty());
+        assert_eq!(data.get_nd(&[0, 0]), None);
+    }
+
+    #[test]
+    fn reads_fortran_order_array() {
+        let bytes = npy_bytes("{'descr': '<u2', 'fortran_order': True, 'shape': (2, 3), }",
+                              &[0, 10, 1, 11, 2, 12]);
+        let data = NpyData::<u16>::from_bytes(&bytes).unwrap();
+        assert_eq!(data.order(), Order::Fortran);
+        assert_eq!(data.get_nd(&[0, 2]), Some(2));
+        assert_eq!(data.get_nd(&[1, 0]), Some(10));
+        assert_eq!(data.get_nd(&[1, 2]), Some(12));
+        assert_eq!(data.to_vec(), vec![0, 10, 1, 11, 2, 12]);
+        assert_eq!(data.to_vec_c_order(), vec![0, 1, 2, 10, 11, 12]);
+    }
+
+    #[test]
+    fn converts_three_dimensional_fortran_array_to_c_order() {
+        let bytes = npy_bytes("{'descr': '<u2', 'fortran_order': True, 'shape': (2, 2, 2), }",
+                              &[0, 100, 10, 110, 1, 101, 11, 111]);
+        let data = NpyData::<u16>::from_bytes(&bytes).unwrap();
+        assert_eq!(data.get_nd(&[1, 0, 1]), Some(101));
+        assert_eq!(data.to_vec_c_order(), vec![0, 1, 10, 11, 100, 101, 110, 111]);
+    }
+
+    #[test]
+    fn reads_big_endian_array() {
+        let bytes = npy_bytes("{'descr': '>u2', 'fortran_order': False, 'shape': (3,), }",
+                              &[0x0100, 0x0200, 0x0a0b]);
+        let data = NpyData::<u16>::from_bytes(&bytes).unwrap();
+        assert_eq!(data.to_vec(), vec![1, 2, 0x0b0a]);
+        assert_eq!(data.get(2), Some(0x0b0a));
+        assert_eq!(data.into_iter().collect::<Vec<_>>(), vec![1, 2, 0x0b0a]);
+    }
+
+    #[test]
+    fn errors_when_data_is_shorter_than_shape() {
+        let bytes = npy_bytes("{'descr': '<u2', 'fortran_order': False, 'shape': (2, 2), }", &[1, 2, 3]);
+        assert!(NpyData::<u16>::from_bytes(&bytes).is_err());
+    }
+
+    #[test]
+    fn errors_when_data_size_overflows() {
+        let bytes = npy_bytes("{'descr': '<u2', 'fortran_order': False, 'shape': (4611686018427387904, 8), }", &[]);
+        assert!(NpyData::<u16>::from_bytes(&bytes).is_err());
+        let bytes = npy_bytes("{'descr': '<u2', 'fortran_order': False, 'shape': (4611686018427387904, 4), }", &[]);
+        assert!(NpyData::<u16>::from_bytes(&bytes).is_err());
+    }
+
+    fn npy_bytes(header: &str, data: &[u16]) -> Vec<u8> {
+        let mut bytes = vec![0x93u8];
+        bytes.extend(b"NUMPY");
+        bytes.extend(&[0x01u8, 0x00, header.len() as u8, 0]);
+        bytes.extend(header.as_bytes());
+        for &x in data {
+            bytes.extend(&[x as u8, (x >> 8) as u8]);
+        }
+        bytes
+    }
+}
@@ -0,0 +1,79 @@
+use std::io::{Result, ErrorKind, Error};
+
+use header::{shape_size, DType, Header, Version};
+use pickle::{unpickle_object_array, PyObject};
+use type_str::split_byte_order;
+
+/// The contents of a NPY file of Python objects, i.e. of the `|O` type.
+///
+/// Numpy stores such arrays as a pickle, which generally can't be read safely. Only arrays of
+/// `str`, `bytes`, `int`, `float`, `bool` and `None` are supported; files containing anything
+/// else are refused.
+///
+/// The objects are always in the C order, regardless of the `fortran_order` of the file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ObjectArray {
+    objects: Vec<PyObject>,
+    shape: Vec<u64>,
+    version: Version,
+}
+
+impl ObjectArray {
+    /// Deserialize a NPY file of Python objects represented as bytes
+    pub fn from_bytes(bytes: &[u8]) -> Result<ObjectArray> {
+        let (header, offset) = Header::parse(bytes)?;
+        if !is_object_dtype(&header.dtype) {
+            return Err(Error::new(ErrorKind::InvalidData,
+                format!("expected an array of objects, found: {:?}", header.dtype)));
+        }
+        let objects = unpickle_object_array(&bytes[offset..])?;
+        if objects.len() != shape_size(&header.shape)? {
+            return Err(Error::new(ErrorKind::InvalidData,
+                format!("found {} objects, but the shape is {:?}", objects.len(), header.shape)));
+        }
+        Ok(ObjectArray { objects, shape: header.shape, version: header.version })
+    }
+
+    /// Shape of the array
+    pub fn shape(&self) -> &[u64] {
+        &self.shape
+    }
+
+    /// Version of the file format
+    pub fn version(&self) -> Version {
+        self.version
+    }
+
+    /// Gets a single object
+    pub fn get(&self, i: usize) -> Option<&PyObject> {
+        self.objects.get(i)
+    }
+
+    /// Returns the number of objects
+    pub fn len(&self) -> usize {
+        self.objects.len()
+    }
+
+    /// Returns whether there are zero objects
+    pub fn is_empty(&self) -> bool {
+        self.objects.is_empty()
+    }
+
+    /// Returns all the objects
+    pub fn objects(&self) -> &[PyObject] {
+        &self.objects
+    }
+
+    /// Converts the array into a vector of its objects
+    pub fn into_vec(self) -> Vec<PyObject> {
+        self.objects
+    }
+}
+
+/// Whether the dtype is Numpy's object type, `|O`
+pub fn is_object_dtype(dtype: &DType) -> bool {
+    match dtype {
+        DType::Plain { ty, shape } => shape.is_empty() && split_byte_order(ty).1 == "O",
+        DType::Record(_) | DType::RecordArray { .. } | DType::OffsetRecord { .. } => false,
+    }
+}
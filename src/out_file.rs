@@ -4,17 +4,16 @@ use std::fs::File;
 use std::path::Path;
 use std::marker::PhantomData;
 
-use byteorder::{WriteBytesExt, LittleEndian};
-
 use serializable::Serializable;
-use header::DType;
+use header::{DType, Order, encode_header, shape_size, shape_to_string};
 
-const FILLER: &'static [u8] = &[42; 19];
+const FILLER: &[u8] = &[42; 19];
 
 /// Serialize into a file one row at a time. To serialize an iterator, use the
 /// [`to_file`](fn.to_file.html) function.
 pub struct OutFile<Row: Serializable> {
     shape_pos: usize,
+    shape: Option<Vec<u64>>,
     len: usize,
     fw: BufWriter<File>,
     _t: PhantomData<Row>
@@ -22,35 +21,55 @@ pub struct OutFile<Row: Serializable> {
 
 impl<Row: Serializable> OutFile<Row> {
     /// Open a file
+    ///
+    /// The rows are stored in a one-dimensional array, whose length is determined by the
+    /// number of rows pushed before closing the file.
+    ///
+    /// The oldest format version able to represent the header is chosen: 1.0 by default, 2.0 if
+    /// the header is longer than 65535 bytes, and 3.0 if field names can't be encoded in Latin-1.
     pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Self> {
-        let dtype = Row::dtype();
-        if let &DType::Plain { ref shape, .. } = &dtype {
-            assert!(shape.len() == 0, "plain non-scalar dtypes not supported");
-        }
-        let mut fw = BufWriter::new(File::create(path)?);
-        fw.write_all(&[0x93u8])?;
-        fw.write_all(b"NUMPY")?;
-        fw.write_all(&[0x01u8, 0x00])?;
-
-        let (header, shape_pos) = create_header(&dtype);
+        Self::open_(path, None, Order::C)
+    }
 
-        let mut padding: Vec<u8> = vec![];
-        padding.extend(&::std::iter::repeat(b' ').take(15 - ((header.len() + 10) % 16)).collect::<Vec<_>>());
-        padding.extend(&[b'\n']);
+    /// Open a file for an array of the given shape, stored in the given order
+    ///
+    /// The rows must be pushed in storage order, i.e. with the first index varying the fastest
+    /// for [`Order::Fortran`](enum.Order.html). Closing the file fails if the number of rows
+    /// doesn't match the shape. Opening fails if the number of rows doesn't fit into `usize`.
+    pub fn open_with_shape<P: AsRef<Path>>(path: P, shape: &[u64], order: Order) -> io::Result<Self> {
+        if shape_size(shape).is_err() {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                format!("the shape {:?} has too many rows", shape)));
+        }
+        Self::open_(path, Some(shape.to_vec()), order)
+    }
 
-        let len = header.len() + padding.len();
-        assert! (len <= ::std::u16::MAX as usize);
-        assert_eq!((len + 10) % 16, 0);
+    fn open_<P: AsRef<Path>>(path: P, shape: Option<Vec<u64>>, order: Order) -> io::Result<Self> {
+        let dtype = Row::dtype();
+        match dtype {
+            DType::Plain { shape: ref ty_shape, .. } if !ty_shape.is_empty() =>
+                return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                    "plain non-scalar dtypes not supported")),
+            DType::RecordArray { .. } =>
+                return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                    "arrays of records are only supported as fields")),
+            _ => {},
+        }
+        // An unknown length is filled in on close
+        let shape_str = match shape {
+            Some(ref shape) => shape_to_string(shape).into_bytes(),
+            None => [FILLER, b","].concat(),
+        };
+        let (header, _, shape_pos) = encode_header(&dtype, order == Order::Fortran, &shape_str, None)?;
 
-        fw.write_u16::<LittleEndian>(len as u16)?;
+        let mut fw = BufWriter::new(File::create(path)?);
         fw.write_all(&header)?;
-        // Padding to 8 bytes
-        fw.write_all(&padding)?;
 
         Ok(OutFile {
-            shape_pos: shape_pos,
+            shape_pos,
+            shape,
             len: 0,
-            fw: fw,
+            fw,
             _t: PhantomData,
         })
     }
@@ -62,13 +81,20 @@ impl<Row: Serializable> OutFile<Row> {
     }
 
     fn close_(&mut self) -> io::Result<()> {
-        // Write the size to the header
-        self.fw.seek(SeekFrom::Start(self.shape_pos as u64))?;
-        let length = format!("{}", self.len);
-        self.fw.write_all(length.as_bytes())?;
-        self.fw.write_all(&b",), }"[..])?;
-        self.fw.write_all(&::std::iter::repeat(b' ').take(FILLER.len() - length.len()).collect::<Vec<_>>())?;
-        Ok(())
+        if let Some(ref shape) = self.shape {
+            if shape_size(shape)? != self.len {
+                return Err(io::Error::new(io::ErrorKind::InvalidData,
+                    format!("{} rows were written, which doesn't match the shape {:?}", self.len, shape)));
+            }
+        } else {
+            // Write the size to the header
+            self.fw.seek(SeekFrom::Start(self.shape_pos as u64))?;
+            let length = format!("{}", self.len);
+            self.fw.write_all(length.as_bytes())?;
+            self.fw.write_all(&b",), }"[..])?;
+            self.fw.write_all(&vec![b' '; FILLER.len() - length.len()])?;
+        }
+        self.fw.flush()
     }
 
     /// Finish writing the file by finalizing the header and closing the file.
@@ -79,17 +105,6 @@ impl<Row: Serializable> OutFile<Row> {
     }
 }
 
-fn create_header(dtype: &DType) -> (Vec<u8>, usize) {
-    let mut header: Vec<u8> = vec![];
-    header.extend(&b"{'descr': "[..]);
-    header.extend(dtype.descr().as_bytes());
-    header.extend(&b", 'fortran_order': False, 'shape': ("[..]);
-    let shape_pos = header.len() + 10;
-    header.extend(FILLER);
-    header.extend(&b",), }"[..]);
-    (header, shape_pos)
-}
-
 impl<Row: Serializable> Drop for OutFile<Row> {
     fn drop(&mut self) {
         let _ = self.close_(); // Ignore the errors
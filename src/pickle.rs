@@ -0,0 +1,460 @@
+use std::collections::HashMap;
+use std::io::{Result, ErrorKind, Error};
+use byteorder::{ByteOrder, LittleEndian, BigEndian};
+
+/// A Python object stored in an object array
+#[derive(Debug, Clone, PartialEq)]
+pub enum PyObject {
+    /// `None`
+    None,
+    /// `bool`
+    Bool(bool),
+    /// `int`, as long as it fits into `i64`
+    Int(i64),
+    /// `float`
+    Float(f64),
+    /// `str`
+    Str(String),
+    /// `bytes`
+    Bytes(Vec<u8>),
+}
+
+/// Containers may be nested at most this deep, which is plenty for the layout of object arrays,
+/// e.g. the array state is a tuple containing the list of items
+const MAX_DEPTH: usize = 3;
+
+/// Strings and bytes copied from the memo may add up to at most this many bytes, so that a small
+/// pickle referencing a large string many times can't make us allocate a huge amount of memory
+const MAX_MEMO_COPIES: usize = 1 << 28;
+
+/// The only globals needed to unpickle a Numpy object array
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Global {
+    Reconstruct,
+    NdArray,
+    DType,
+}
+
+#[derive(Debug, Clone)]
+enum Value {
+    Object(PyObject),
+    Tuple(Vec<Value>),
+    List(Vec<Value>),
+    Global(Global),
+    /// An array under construction, with its items once they are set
+    Array(Option<Vec<Value>>),
+    /// A dtype, with its type string, e.g. `O8`
+    DType(String),
+}
+
+/// Unpickle the items of an object array pickled by `numpy.save`.
+///
+/// Only the subset of the pickle protocol used by Numpy for object arrays is understood, and the
+/// only allowed globals are those reconstructing the array. Anything else is refused, so that
+/// no code is ever executed.
+pub fn unpickle_object_array(bytes: &[u8]) -> Result<Vec<PyObject>> {
+    unpickle(bytes, MAX_MEMO_COPIES)
+}
+
+fn unpickle(bytes: &[u8], memo_budget: usize) -> Result<Vec<PyObject>> {
+    let mut machine = Machine { bytes, pos: 0, stack: vec![], marks: vec![], memo: HashMap::new(), memo_budget };
+    match machine.run()? {
+        Value::Array(Some(items)) => items.into_iter().map(|item| match item {
+            Value::Object(object) => Ok(object),
+            other => invalid(&format!("unsupported {} in the array", other.kind())),
+        }).collect(),
+        other => invalid(&format!("the pickle doesn't contain an array, but a {}", other.kind())),
+    }
+}
+
+impl Value {
+    /// What kind of value this is, for error messages. The values themselves aren't formatted,
+    /// since they can be huge.
+    fn kind(&self) -> &'static str {
+        match *self {
+            Value::Object(_) => "object",
+            Value::Tuple(_) => "tuple",
+            Value::List(_) => "list",
+            Value::Global(_) => "global",
+            Value::Array(_) => "array",
+            Value::DType(_) => "dtype",
+        }
+    }
+
+    /// How deeply containers are nested in the value
+    fn depth(&self) -> usize {
+        match *self {
+            Value::Tuple(ref items) | Value::List(ref items) | Value::Array(Some(ref items)) =>
+                1 + items.iter().map(Value::depth).max().unwrap_or(0),
+            Value::Object(_) | Value::Global(_) | Value::Array(None) | Value::DType(_) => 0,
+        }
+    }
+}
+
+struct Machine<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+    stack: Vec<Value>,
+    marks: Vec<usize>,
+    memo: HashMap<u32, Option<Value>>,
+    /// How many more bytes of strings may be copied from the memo
+    memo_budget: usize,
+}
+
+impl<'a> Machine<'a> {
+    fn run(&mut self) -> Result<Value> {
+        loop {
+            let opcode = self.take(1)?[0];
+            match opcode {
+                // PROTO
+                0x80 => { self.take(1)?; },
+                // FRAME
+                0x95 => { self.take(8)?; },
+                // STOP
+                b'.' => return self.pop(),
+
+                // MARK
+                b'(' => self.marks.push(self.stack.len()),
+                // BINPUT, LONG_BINPUT, MEMOIZE
+                b'q' => { let i = self.take(1)?[0] as u32; self.put(i)?; },
+                b'r' => { let i = self.take_u32()?; self.put(i)?; },
+                0x94 => { let i = self.memo.len() as u32; self.put(i)?; },
+                // BINGET, LONG_BINGET
+                b'h' => { let i = self.take(1)?[0] as u32; self.get(i)?; },
+                b'j' => { let i = self.take_u32()?; self.get(i)?; },
+
+                // NONE, NEWTRUE, NEWFALSE
+                b'N' => self.push(PyObject::None),
+                0x88 => self.push(PyObject::Bool(true)),
+                0x89 => self.push(PyObject::Bool(false)),
+                // BININT, BININT1, BININT2
+                b'J' => { let n = LittleEndian::read_i32(self.take(4)?); self.push(PyObject::Int(n as i64)); },
+                b'K' => { let n = self.take(1)?[0]; self.push(PyObject::Int(n as i64)); },
+                b'M' => { let n = LittleEndian::read_u16(self.take(2)?); self.push(PyObject::Int(n as i64)); },
+                // LONG1, LONG4
+                0x8a => { let n = self.take(1)?[0] as usize; self.long(n)?; },
+                0x8b => { let n = self.take_u32()? as usize; self.long(n)?; },
+                // BINFLOAT
+                b'G' => { let x = BigEndian::read_f64(self.take(8)?); self.push(PyObject::Float(x)); },
+                // SHORT_BINUNICODE, BINUNICODE, BINUNICODE8
+                0x8c => { let n = self.take(1)?[0] as usize; self.unicode(n)?; },
+                b'X' => { let n = self.take_u32()? as usize; self.unicode(n)?; },
+                0x8d => { let n = self.take_len64()?; self.unicode(n)?; },
+                // SHORT_BINBYTES, BINBYTES, BINBYTES8
+                b'C' => { let n = self.take(1)?[0] as usize; self.binbytes(n)?; },
+                b'B' => { let n = self.take_u32()? as usize; self.binbytes(n)?; },
+                0x8e => { let n = self.take_len64()?; self.binbytes(n)?; },
+
+                // EMPTY_TUPLE, TUPLE1, TUPLE2, TUPLE3, TUPLE
+                b')' => self.stack.push(Value::Tuple(vec![])),
+                0x85 => { let items = self.pop_n(1)?; self.push_nested(Value::Tuple(items))?; },
+                0x86 => { let items = self.pop_n(2)?; self.push_nested(Value::Tuple(items))?; },
+                0x87 => { let items = self.pop_n(3)?; self.push_nested(Value::Tuple(items))?; },
+                b't' => { let items = self.pop_mark()?; self.push_nested(Value::Tuple(items))?; },
+                // EMPTY_LIST, LIST, APPEND, APPENDS
+                b']' => self.stack.push(Value::List(vec![])),
+                b'l' => { let items = self.pop_mark()?; self.push_nested(Value::List(items))?; },
+                b'a' => { let items = self.pop_n(1)?; self.append(items)?; },
+                b'e' => { let items = self.pop_mark()?; self.append(items)?; },
+
+                // GLOBAL, STACK_GLOBAL
+                b'c' => {
+                    let module = self.take_line()?;
+                    let name = self.take_line()?;
+                    let global = find_global(&module, &name)?;
+                    self.stack.push(Value::Global(global));
+                },
+                0x93 => {
+                    let name = self.pop()?;
+                    let module = self.pop()?;
+                    match (module, name) {
+                        (Value::Object(PyObject::Str(module)), Value::Object(PyObject::Str(name))) => {
+                            let global = find_global(&module, &name)?;
+                            self.stack.push(Value::Global(global));
+                        },
+                        _ => return invalid("STACK_GLOBAL expects two strings"),
+                    }
+                },
+                // REDUCE
+                b'R' => {
+                    let args = self.pop()?;
+                    let callable = self.pop()?;
+                    let value = reduce(callable, args)?;
+                    self.push_nested(value)?;
+                },
+                // BUILD
+                b'b' => {
+                    let state = self.pop()?;
+                    let value = build(self.pop()?, state)?;
+                    self.push_nested(value)?;
+                },
+
+                _ => return invalid(&format!("unsupported pickle opcode {:#04x}", opcode)),
+            }
+        }
+    }
+
+    fn take(&mut self, n: usize) -> Result<&'a [u8]> {
+        if self.bytes.len() - self.pos < n {
+            return invalid("unexpected end of the pickle");
+        }
+        let bytes = &self.bytes[self.pos..self.pos + n];
+        self.pos += n;
+        Ok(bytes)
+    }
+
+    fn take_u32(&mut self) -> Result<u32> {
+        Ok(LittleEndian::read_u32(self.take(4)?))
+    }
+
+    fn take_len64(&mut self) -> Result<usize> {
+        let n = LittleEndian::read_u64(self.take(8)?);
+        if n > (self.bytes.len() - self.pos) as u64 {
+            return invalid("unexpected end of the pickle");
+        }
+        Ok(n as usize)
+    }
+
+    fn take_line(&mut self) -> Result<String> {
+        let len = match self.bytes[self.pos..].iter().position(|&b| b == b'\n') {
+            Some(len) => len,
+            None => return invalid("unexpected end of the pickle"),
+        };
+        let line = String::from_utf8_lossy(self.take(len)?).into_owned();
+        self.take(1)?;
+        Ok(line)
+    }
+
+    fn push(&mut self, object: PyObject) {
+        self.stack.push(Value::Object(object));
+    }
+
+    /// Push a container, unless it's nested too deeply. Deeply nested values would overflow the
+    /// stack when they are dropped.
+    fn push_nested(&mut self, value: Value) -> Result<()> {
+        if value.depth() > MAX_DEPTH {
+            return invalid("the pickle is nested too deeply");
+        }
+        self.stack.push(value);
+        Ok(())
+    }
+
+    fn pop(&mut self) -> Result<Value> {
+        match self.stack.pop() {
+            Some(value) => Ok(value),
+            None => invalid("pickle stack underflow"),
+        }
+    }
+
+    fn pop_n(&mut self, n: usize) -> Result<Vec<Value>> {
+        if self.stack.len() < n {
+            return invalid("pickle stack underflow");
+        }
+        let start = self.stack.len() - n;
+        Ok(self.stack.split_off(start))
+    }
+
+    fn pop_mark(&mut self) -> Result<Vec<Value>> {
+        match self.marks.pop() {
+            Some(mark) if mark <= self.stack.len() => Ok(self.stack.split_off(mark)),
+            _ => invalid("pickle mark not found"),
+        }
+    }
+
+    /// Only simple objects are memoized, since cloning containers on every reference could
+    /// make a small pickle expand into a huge amount of memory
+    fn put(&mut self, i: u32) -> Result<()> {
+        let value = match self.stack.last() {
+            Some(value @ Value::Object(_)) | Some(value @ Value::Global(_)) => Some(value.clone()),
+            Some(_) => None,
+            None => return invalid("pickle stack underflow"),
+        };
+        if let Some(ref value) = value {
+            self.charge_copy(value)?;
+        }
+        self.memo.insert(i, value);
+        Ok(())
+    }
+
+    fn get(&mut self, i: u32) -> Result<()> {
+        let value = match self.memo.get(&i) {
+            Some(Some(value)) => value.clone(),
+            Some(None) => return invalid("references to pickled containers are not supported"),
+            None => return invalid(&format!("pickle memo {} not found", i)),
+        };
+        self.charge_copy(&value)?;
+        self.stack.push(value);
+        Ok(())
+    }
+
+    /// Account for a copy of a string made by the memo, failing once there have been too many
+    fn charge_copy(&mut self, value: &Value) -> Result<()> {
+        let size = match *value {
+            Value::Object(PyObject::Str(ref string)) => string.len(),
+            Value::Object(PyObject::Bytes(ref bytes)) => bytes.len(),
+            _ => 0,
+        };
+        if size > self.memo_budget {
+            return invalid("the pickle copies its strings too many times");
+        }
+        self.memo_budget -= size;
+        Ok(())
+    }
+
+    fn append(&mut self, items: Vec<Value>) -> Result<()> {
+        if items.iter().any(|item| item.depth() >= MAX_DEPTH) {
+            return invalid("the pickle is nested too deeply");
+        }
+        match self.stack.last_mut() {
+            Some(Value::List(list)) => {
+                list.extend(items);
+                Ok(())
+            },
+            _ => invalid("can only append to a list"),
+        }
+    }
+
+    /// A little-endian two's complement integer of `n` bytes
+    fn long(&mut self, n: usize) -> Result<()> {
+        if n > 8 {
+            return invalid("integer too large");
+        }
+        let bytes = self.take(n)?;
+        let value = if n == 0 { 0 } else { LittleEndian::read_int(bytes, n) };
+        self.push(PyObject::Int(value));
+        Ok(())
+    }
+
+    fn unicode(&mut self, n: usize) -> Result<()> {
+        match String::from_utf8(self.take(n)?.to_vec()) {
+            Ok(string) => { self.push(PyObject::Str(string)); Ok(()) },
+            Err(_) => invalid("invalid UTF-8 in a pickled string"),
+        }
+    }
+
+    fn binbytes(&mut self, n: usize) -> Result<()> {
+        let bytes = self.take(n)?.to_vec();
+        self.push(PyObject::Bytes(bytes));
+        Ok(())
+    }
+}
+
+fn find_global(module: &str, name: &str) -> Result<Global> {
+    match (module, name) {
+        ("numpy.core.multiarray", "_reconstruct") | ("numpy._core.multiarray", "_reconstruct") =>
+            Ok(Global::Reconstruct),
+        ("numpy", "ndarray") => Ok(Global::NdArray),
+        ("numpy", "dtype") => Ok(Global::DType),
+        _ => invalid(&format!("refusing to unpickle '{}.{}'", module, name)),
+    }
+}
+
+fn reduce(callable: Value, args: Value) -> Result<Value> {
+    match (callable, args) {
+        (Value::Global(Global::Reconstruct), Value::Tuple(ref args))
+            if args.first().is_some_and(|a| matches!(a, Value::Global(Global::NdArray))) =>
+            Ok(Value::Array(None)),
+        (Value::Global(Global::DType), Value::Tuple(ref args)) => match args.first() {
+            Some(Value::Object(PyObject::Str(ty))) => Ok(Value::DType(ty.clone())),
+            _ => invalid("invalid dtype arguments"),
+        },
+        (Value::Global(global), _) => invalid(&format!("refusing to call {:?} with these arguments", global)),
+        (callable, _) => invalid(&format!("refusing to call a {}", callable.kind())),
+    }
+}
+
+fn build(value: Value, state: Value) -> Result<Value> {
+    match (value, state) {
+        (Value::DType(ty), _) => Ok(Value::DType(ty)),
+        // The array state is `(version, shape, dtype, is_fortran, items)`
+        (Value::Array(None), Value::Tuple(mut state)) => match state.pop() {
+            Some(Value::List(items)) => match state.get(2) {
+                Some(Value::DType(ty)) if ty.starts_with('O') => Ok(Value::Array(Some(items))),
+                _ => invalid("only arrays of objects can be unpickled"),
+            },
+            _ => invalid("only arrays of objects can be unpickled"),
+        },
+        (value, _) => invalid(&format!("can't set the state of a {}", value.kind())),
+    }
+}
+
+fn invalid<T>(message: &str) -> Result<T> {
+    Err(Error::new(ErrorKind::InvalidData, message.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `numpy.array(['a', None, 1, 2.5, b'x', s, s, -70000, 2**40, True], dtype=object)` pickled
+    /// by `numpy.save`, where `s = 'repeated'`
+    const PICKLE: &[u8] = b"\x80\x03cnumpy.core.multiarray\n_reconstruct\nq\x00cnumpy\nndarray\nq\x01K\x00\
+        \x85q\x02C\x01bq\x03\x87q\x04Rq\x05(K\x01K\n\x85q\x06cnumpy\ndtype\nq\x07X\x02\x00\x00\x00O8q\x08\
+        \x89\x88\x87q\tRq\n(K\x03X\x01\x00\x00\x00|q\x0bNNNJ\xff\xff\xff\xffJ\xff\xff\xff\xffK?tq\x0cb\
+        \x89]q\r(X\x01\x00\x00\x00aq\x0eNK\x01G@\x04\x00\x00\x00\x00\x00\x00C\x01xq\x0f\
+        X\x08\x00\x00\x00repeatedq\x10h\x10J\x90\xee\xfe\xff\x8a\x06\x00\x00\x00\x00\x00\x01\x88etq\x11b.";
+
+    #[test]
+    fn unpickles_object_array() {
+        let repeated = PyObject::Str("repeated".to_string());
+        assert_eq!(unpickle_object_array(PICKLE).unwrap(), vec![
+            PyObject::Str("a".to_string()),
+            PyObject::None,
+            PyObject::Int(1),
+            PyObject::Float(2.5),
+            PyObject::Bytes(b"x".to_vec()),
+            repeated.clone(),
+            repeated,
+            PyObject::Int(-70000),
+            PyObject::Int(1 << 40),
+            PyObject::Bool(true),
+        ]);
+    }
+
+    #[test]
+    fn refuses_other_globals() {
+        let pickle = b"\x80\x03cos\nsystem\nq\x00X\x02\x00\x00\x00lsq\x01\x85q\x02Rq\x03.";
+        let err = unpickle_object_array(pickle).unwrap_err();
+        assert_eq!(err.to_string(), "refusing to unpickle 'os.system'");
+    }
+
+    #[test]
+    fn refuses_nested_containers() {
+        // Replace the `None` item by an empty list
+        let mut pickle = PICKLE.to_vec();
+        let pos = pickle.windows(3).position(|w| w == b"\x0eNK").unwrap() + 1;
+        pickle[pos] = b']';
+        assert!(unpickle_object_array(&pickle).is_err());
+    }
+
+    #[test]
+    fn refuses_deeply_nested_tuples() {
+        let mut pickle = vec![b'N'];
+        pickle.extend(vec![0x85; 2_000_000]);
+        pickle.push(b'.');
+        let err = unpickle_object_array(&pickle).unwrap_err();
+        assert_eq!(err.to_string(), "the pickle is nested too deeply");
+    }
+
+    #[test]
+    fn limits_copies_of_memoized_strings() {
+        // A 1000-byte string, memoized and then fetched from the memo 100 times
+        let mut pickle = vec![b'X', 0xe8, 0x03, 0, 0];
+        pickle.extend(vec![b'x'; 1000]);
+        pickle.extend(b"q\x00");
+        for _ in 0..100 {
+            pickle.extend(b"h\x00");
+        }
+        pickle.push(b'.');
+        let err = unpickle(&pickle, 50_000).unwrap_err();
+        assert_eq!(err.to_string(), "the pickle copies its strings too many times");
+        assert!(unpickle(PICKLE, 100).is_ok());
+    }
+
+    #[test]
+    fn errors_on_truncated_pickle() {
+        for len in 0..PICKLE.len() {
+            assert!(unpickle_object_array(&PICKLE[..len]).is_err());
+        }
+    }
+}
@@ -1,7 +1,8 @@
 
-use std::io::{Write,Result};
+use std::io::{Write, Result, Error, ErrorKind};
 use byteorder::{WriteBytesExt, LittleEndian};
-use header::DType;
+use header::{DType, Field};
+use convert::FieldDefaults;
 use byteorder::ByteOrder;
 
 /// This trait contains information on how to serialize and deserialize a type.
@@ -22,18 +23,62 @@ pub trait Serializable : Sized {
 
     /// Serialize a single data field into a writer.
     fn write<W: Write>(&self, writer: &mut W) -> Result<()>;
+
+    /// Check that a single data field holds a valid value, e.g. that a `bool` is either 0 or 1.
+    ///
+    /// [`read`](#tymethod.read) should accept any data, but it may be lenient for invalid values.
+    /// This function is used for strict reading, and the default implementation accepts
+    /// everything.
+    #[inline]
+    fn validate(_buf: &[u8]) -> Result<()> {
+        Ok(())
+    }
+
+    /// Default values of record fields, which are used if the fields are missing when reading by
+    /// name using [`NpyData::from_bytes_by_name`](struct.NpyData.html#method.from_bytes_by_name).
+    ///
+    /// Each field is identified by the path of field names leading to it, and its value is
+    /// serialized by [`write`](#tymethod.write). The derived implementation contains the fields
+    /// marked by `#[npy(default)]`, and the default implementation contains nothing.
+    fn field_defaults() -> FieldDefaults {
+        vec![]
+    }
+}
+
+impl Serializable for bool {
+    #[inline]
+    fn dtype() -> DType {
+        DType::Plain { ty: "|b1".to_string(), shape: vec![] }
+    }
+    #[inline]
+    fn n_bytes() -> usize { 1 }
+    #[inline]
+    fn read(buf: &[u8]) -> Self {
+        buf[0] != 0
+    }
+    #[inline]
+    fn write<W: Write>(&self, writer: &mut W) -> Result<()> {
+        writer.write_u8(*self as u8)
+    }
+    #[inline]
+    fn validate(buf: &[u8]) -> Result<()> {
+        match buf[0] {
+            0 | 1 => Ok(()),
+            n => Err(Error::new(ErrorKind::InvalidData, format!("{} is not a valid bool", n))),
+        }
+    }
 }
 
 impl Serializable for i8 {
     #[inline]
     fn dtype() -> DType {
-        DType::Plain { ty: "<i1".to_string(), shape: vec![] }
+        DType::Plain { ty: "|i1".to_string(), shape: vec![] }
     }
     #[inline]
     fn n_bytes() -> usize { 1 }
     #[inline]
     fn read(buf: &[u8]) -> Self {
-        unsafe { ::std::mem::transmute(buf[0]) } // TODO: a better way
+        buf[0] as i8
     }
     #[inline]
     fn write<W: Write>(&self, writer: &mut W) -> Result<()> {
@@ -95,7 +140,7 @@ impl Serializable for i64 {
 impl Serializable for u8 {
     #[inline]
     fn dtype() -> DType {
-        DType::Plain { ty: "<u1".to_string(), shape: vec![] }
+        DType::Plain { ty: "|u1".to_string(), shape: vec![] }
     }
     #[inline]
     fn n_bytes() -> usize { 1 }
@@ -194,41 +239,145 @@ impl Serializable for f64 {
     }
 }
 
-macro_rules! gen_array_serializable {
-    ($($n:tt),+) => { $(
-        impl<T: Serializable + Default + Copy> Serializable for [T; $n] {
+#[cfg(feature = "half")]
+impl Serializable for ::half::f16 {
+    #[inline]
+    fn dtype() -> DType {
+        DType::Plain { ty: "<f2".to_string(), shape: vec![] }
+    }
+    #[inline]
+    fn n_bytes() -> usize { 2 }
+    #[inline]
+    fn read(buf: &[u8]) -> Self {
+        ::half::f16::from_bits(LittleEndian::read_u16(buf))
+    }
+    #[inline]
+    fn write<W: Write>(&self, writer: &mut W) -> Result<()> {
+        writer.write_u16::<LittleEndian>(self.to_bits())
+    }
+}
+
+/// Numpy has no `bfloat16` type, so its bits are stored as `uint16`. This impl is only
+/// available with the `bf16` feature, since it makes `bf16` indistinguishable from `u16` in
+/// the file.
+#[cfg(feature = "bf16")]
+impl Serializable for ::half::bf16 {
+    #[inline]
+    fn dtype() -> DType {
+        DType::Plain { ty: "<u2".to_string(), shape: vec![] }
+    }
+    #[inline]
+    fn n_bytes() -> usize { 2 }
+    #[inline]
+    fn read(buf: &[u8]) -> Self {
+        ::half::bf16::from_bits(LittleEndian::read_u16(buf))
+    }
+    #[inline]
+    fn write<W: Write>(&self, writer: &mut W) -> Result<()> {
+        writer.write_u16::<LittleEndian>(self.to_bits())
+    }
+}
+
+impl<T: Serializable, const N: usize> Serializable for [T; N] {
+    /// The array dimension is the outermost one, so `[[T; 3]; 5]` has the shape `(5, 3)`
+    #[inline]
+    fn dtype() -> DType {
+        use DType::*;
+        let prepend = |shape: &[u64]| Some(N as u64).into_iter().chain(shape.iter().cloned()).collect();
+        match T::dtype() {
+            Plain { ref ty, ref shape } => Plain { ty: ty.clone(), shape: prepend(shape) },
+            record @ Record(_) | record @ OffsetRecord { .. } =>
+                RecordArray { record: Box::new(record), shape: vec![N as u64] },
+            RecordArray { record, ref shape } => RecordArray { record, shape: prepend(shape) },
+        }
+    }
+    #[inline]
+    fn n_bytes() -> usize { T::n_bytes() * N }
+    #[inline]
+    fn read(buf: &[u8]) -> Self {
+        ::std::array::from_fn(|i| T::read(&buf[i * T::n_bytes()..]))
+    }
+    #[inline]
+    fn write<W: Write>(&self, writer: &mut W) -> Result<()> {
+        for item in self {
+            item.write(writer)?;
+        }
+        Ok(())
+    }
+    #[inline]
+    fn validate(buf: &[u8]) -> Result<()> {
+        for i in 0..N {
+            T::validate(&buf[i * T::n_bytes()..])?;
+        }
+        Ok(())
+    }
+    fn field_defaults() -> FieldDefaults {
+        T::field_defaults()
+    }
+}
+
+/// Tuples map to record types with Numpy's default field names `f0`, `f1`, etc.
+macro_rules! gen_tuple_serializable {
+    ($(($($t:ident $i:tt),+))+) => { $(
+        impl<$($t: Serializable),+> Serializable for ($($t,)+) {
             #[inline]
             fn dtype() -> DType {
-                use DType::*;
-                match T::dtype() {
-                    Plain { ref ty, ref shape } => DType::Plain {
-                        ty: ty.clone(),
-                        shape: shape.clone().into_iter().chain(Some($n)).collect()
-                    },
-                    Record(_) => unimplemented!("arrays of nested records")
-                }
+                DType::Record(vec![$(
+                    Field { name: format!("f{}", $i), dtype: $t::dtype() }
+                ),+])
             }
             #[inline]
-            fn n_bytes() -> usize { T::n_bytes() * $n }
+            fn n_bytes() -> usize { 0 $(+ $t::n_bytes())+ }
             #[inline]
+            #[allow(unused_assignments)]
             fn read(buf: &[u8]) -> Self {
-                let mut a = [T::default(); $n];
-                let mut off = 0;
-                for x in &mut a {
-                    *x = T::read(&buf[off..]);
-                    off += T::n_bytes();
-                }
-                a
+                let mut offset = 0;
+                ($({
+                    let item = $t::read(&buf[offset..]);
+                    offset += $t::n_bytes();
+                    item
+                },)+)
             }
             #[inline]
             fn write<W: Write>(&self, writer: &mut W) -> Result<()> {
-                for item in self {
-                    item.write(writer)?;
-                }
+                $( self.$i.write(writer)?; )+
+                Ok(())
+            }
+            #[inline]
+            #[allow(unused_assignments)]
+            fn validate(buf: &[u8]) -> Result<()> {
+                let mut offset = 0;
+                $(
+                    $t::validate(&buf[offset..])?;
+                    offset += $t::n_bytes();
+                )+
                 Ok(())
             }
+            fn field_defaults() -> FieldDefaults {
+                let mut defaults = vec![];
+                $(
+                    for (mut path, bytes) in $t::field_defaults() {
+                        path.insert(0, format!("f{}", $i));
+                        defaults.push((path, bytes));
+                    }
+                )+
+                defaults
+            }
         }
     )+ }
 }
 
-gen_array_serializable!(1,2,3,4,5,6,7,8,9,10,11,12,13,14,15,16);
+gen_tuple_serializable! {
+    (A 0)
+    (A 0, B 1)
+    (A 0, B 1, C 2)
+    (A 0, B 1, C 2, D 3)
+    (A 0, B 1, C 2, D 3, E 4)
+    (A 0, B 1, C 2, D 3, E 4, F 5)
+    (A 0, B 1, C 2, D 3, E 4, F 5, G 6)
+    (A 0, B 1, C 2, D 3, E 4, F 5, G 6, H 7)
+    (A 0, B 1, C 2, D 3, E 4, F 5, G 6, H 7, I 8)
+    (A 0, B 1, C 2, D 3, E 4, F 5, G 6, H 7, I 8, J 9)
+    (A 0, B 1, C 2, D 3, E 4, F 5, G 6, H 7, I 8, J 9, K 10)
+    (A 0, B 1, C 2, D 3, E 4, F 5, G 6, H 7, I 8, J 9, K 10, L 11)
+}
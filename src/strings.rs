@@ -0,0 +1,205 @@
+use std::convert::TryFrom;
+use std::fmt;
+use std::io::{Write, Result, Error, ErrorKind};
+use byteorder::{WriteBytesExt, LittleEndian, ByteOrder};
+
+use header::DType;
+use serializable::Serializable;
+
+/// A byte string of at most `N` bytes, mapping to Numpy's `|SN` type.
+///
+/// Shorter strings are padded with null bytes, which are stripped by
+/// [`as_bytes`](#method.as_bytes), just like in Numpy.
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub struct FixedBytes<const N: usize>([u8; N]);
+
+impl<const N: usize> FixedBytes<N> {
+    /// Returns the bytes without the trailing null padding
+    pub fn as_bytes(&self) -> &[u8] {
+        let len = self.0.iter().rposition(|&b| b != 0).map_or(0, |i| i + 1);
+        &self.0[..len]
+    }
+
+    /// Returns all the `N` bytes, including the null padding
+    pub fn as_padded_bytes(&self) -> &[u8; N] {
+        &self.0
+    }
+}
+
+impl<const N: usize> Default for FixedBytes<N> {
+    fn default() -> Self {
+        FixedBytes([0; N])
+    }
+}
+
+impl<const N: usize> fmt::Debug for FixedBytes<N> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "FixedBytes({:?})", String::from_utf8_lossy(self.as_bytes()))
+    }
+}
+
+impl<'a, const N: usize> TryFrom<&'a [u8]> for FixedBytes<N> {
+    type Error = Error;
+
+    /// Pad the bytes with nulls. Fails if there are more than `N` bytes.
+    fn try_from(bytes: &'a [u8]) -> Result<Self> {
+        if bytes.len() > N {
+            return Err(Error::new(ErrorKind::InvalidInput,
+                format!("{} bytes don't fit into FixedBytes<{}>", bytes.len(), N)));
+        }
+        let mut padded = [0; N];
+        padded[..bytes.len()].copy_from_slice(bytes);
+        Ok(FixedBytes(padded))
+    }
+}
+
+impl<const N: usize> Serializable for FixedBytes<N> {
+    #[inline]
+    fn dtype() -> DType {
+        DType::Plain { ty: format!("|S{}", N), shape: vec![] }
+    }
+    #[inline]
+    fn n_bytes() -> usize { N }
+    #[inline]
+    fn read(buf: &[u8]) -> Self {
+        let mut bytes = [0; N];
+        bytes.copy_from_slice(&buf[..N]);
+        FixedBytes(bytes)
+    }
+    #[inline]
+    fn write<W: Write>(&self, writer: &mut W) -> Result<()> {
+        writer.write_all(&self.0)
+    }
+}
+
+/// A unicode string of at most `N` characters, mapping to Numpy's `<UN` type.
+///
+/// The characters are stored in UCS-4 and shorter strings are padded with nulls, which are
+/// stripped when converting to a `String`, just like in Numpy.
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub struct FixedStr<const N: usize>([char; N]);
+
+impl<const N: usize> FixedStr<N> {
+    /// Returns all the `N` characters, including the null padding
+    pub fn as_padded_chars(&self) -> &[char; N] {
+        &self.0
+    }
+}
+
+impl<const N: usize> Default for FixedStr<N> {
+    fn default() -> Self {
+        FixedStr(['\0'; N])
+    }
+}
+
+impl<const N: usize> fmt::Display for FixedStr<N> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let len = self.0.iter().rposition(|&c| c != '\0').map_or(0, |i| i + 1);
+        for c in &self.0[..len] {
+            write!(f, "{}", c)?;
+        }
+        Ok(())
+    }
+}
+
+impl<const N: usize> fmt::Debug for FixedStr<N> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "FixedStr({:?})", self.to_string())
+    }
+}
+
+impl<'a, const N: usize> TryFrom<&'a str> for FixedStr<N> {
+    type Error = Error;
+
+    /// Pad the string with nulls. Fails if there are more than `N` characters.
+    fn try_from(string: &'a str) -> Result<Self> {
+        let mut padded = ['\0'; N];
+        for (i, c) in string.chars().enumerate() {
+            if i == N {
+                return Err(Error::new(ErrorKind::InvalidInput,
+                    format!("{:?} doesn't fit into FixedStr<{}>", string, N)));
+            }
+            padded[i] = c;
+        }
+        Ok(FixedStr(padded))
+    }
+}
+
+impl<const N: usize> From<FixedStr<N>> for String {
+    fn from(string: FixedStr<N>) -> String {
+        string.to_string()
+    }
+}
+
+impl<const N: usize> Serializable for FixedStr<N> {
+    #[inline]
+    fn dtype() -> DType {
+        DType::Plain { ty: format!("<U{}", N), shape: vec![] }
+    }
+    #[inline]
+    fn n_bytes() -> usize { 4 * N }
+    /// Invalid code points are replaced by `U+FFFD`
+    #[inline]
+    fn read(buf: &[u8]) -> Self {
+        let mut chars = ['\0'; N];
+        for (i, c) in chars.iter_mut().enumerate() {
+            let code = LittleEndian::read_u32(&buf[4 * i..]);
+            *c = ::std::char::from_u32(code).unwrap_or(::std::char::REPLACEMENT_CHARACTER);
+        }
+        FixedStr(chars)
+    }
+    #[inline]
+    fn write<W: Write>(&self, writer: &mut W) -> Result<()> {
+        for &c in &self.0 {
+            writer.write_u32::<LittleEndian>(c as u32)?;
+        }
+        Ok(())
+    }
+    #[inline]
+    fn validate(buf: &[u8]) -> Result<()> {
+        for i in 0..N {
+            let code = LittleEndian::read_u32(&buf[4 * i..]);
+            if ::std::char::from_u32(code).is_none() {
+                return Err(Error::new(ErrorKind::InvalidData,
+                    format!("{:#x} is not a valid unicode code point", code)));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn strips_null_padding_of_bytes() {
+        let bytes = FixedBytes::<6>::try_from(&b"abc"[..]).unwrap();
+        assert_eq!(bytes.as_bytes(), b"abc");
+        assert_eq!(bytes.as_padded_bytes(), b"abc\0\0\0");
+        assert_eq!(FixedBytes::<6>::read(b"ab\0c\0\0").as_bytes(), b"ab\0c");
+        assert!(FixedBytes::<2>::try_from(&b"abc"[..]).is_err());
+    }
+
+    #[test]
+    fn converts_unicode_strings() {
+        let string = FixedStr::<4>::try_from("\u{e9}t\u{e9}").unwrap();
+        assert_eq!(string.to_string(), "\u{e9}t\u{e9}");
+        assert_eq!(String::from(string), "\u{e9}t\u{e9}");
+        assert!(FixedStr::<2>::try_from("abc").is_err());
+
+        let mut buf = vec![];
+        string.write(&mut buf).unwrap();
+        assert_eq!(buf.len(), FixedStr::<4>::n_bytes());
+        assert_eq!(&buf[..8], &[0xe9, 0, 0, 0, b't', 0, 0, 0]);
+        assert_eq!(FixedStr::<4>::read(&buf), string);
+    }
+
+    #[test]
+    fn validates_code_points() {
+        let buf = [0x00, 0xd8, 0, 0, b'a', 0, 0, 0];
+        assert!(FixedStr::<2>::validate(&buf).is_err());
+        assert_eq!(FixedStr::<2>::read(&buf).to_string(), "\u{fffd}a");
+        assert!(FixedStr::<1>::validate(&buf[4..]).is_ok());
+    }
+}
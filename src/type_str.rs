@@ -0,0 +1,57 @@
+use std::io::{Result, ErrorKind, Error};
+
+/// Split a type string into its byte order character and the rest, e.g. `<f8` into `'<'` and
+/// `f8`
+pub fn split_byte_order(ty: &str) -> (Option<char>, &str) {
+    match ty.chars().next() {
+        Some(c @ '<') | Some(c @ '>') | Some(c @ '|') | Some(c @ '=') => (Some(c), &ty[1..]),
+        _ => (None, ty),
+    }
+}
+
+/// Resolve the byte order character into `'<'` or `'>'`. The native byte order is used if
+/// it's `'='` or missing. Returns `None` for `'|'`, which denotes types without a byte order.
+pub fn native_byte_order(order: Option<char>) -> Option<char> {
+    match order {
+        Some('|') => None,
+        Some('<') | Some('>') => order,
+        _ => if cfg!(target_endian = "little") { Some('<') } else { Some('>') },
+    }
+}
+
+/// Split a type string without the byte order into its kind and item size, e.g. `U8` into
+/// `'U'` and `8`. Any unit suffix, such as in `M8[ns]`, is ignored.
+pub fn split_kind(ty: &str) -> Result<(char, usize)> {
+    let kind = match ty.chars().next() {
+        Some(kind) if kind.is_ascii_alphabetic() => kind,
+        _ => return invalid_data(&format!("invalid type string '{}'", ty)),
+    };
+    let digits = ty[1..].chars().take_while(|c| c.is_ascii_digit()).count();
+    match ty[1..1 + digits].parse() {
+        Ok(size) => Ok((kind, size)),
+        Err(_) => invalid_data(&format!("type string '{}' has no size", ty)),
+    }
+}
+
+/// Number of bytes of a single item of a type string without the byte order
+pub fn item_size(ty: &str) -> Result<usize> {
+    match split_kind(ty)? {
+        // The size of unicode strings is given in UCS-4 characters
+        ('U', n) => n.checked_mul(4).map_or_else(|| invalid_data("the string type is too large"), Ok),
+        (_, n) => Ok(n),
+    }
+}
+
+/// Size of the units whose bytes are reversed when changing the byte order
+pub fn swap_unit(ty: &str) -> Result<usize> {
+    match split_kind(ty)? {
+        ('c', n) => Ok(n / 2),
+        ('U', _) => Ok(4),
+        ('S', _) | ('a', _) | ('V', _) => Ok(1),
+        (_, n) => Ok(n),
+    }
+}
+
+fn invalid_data<T>(message: &str) -> Result<T> {
+    Err(Error::new(ErrorKind::InvalidData, message.to_string()))
+}
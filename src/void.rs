@@ -0,0 +1,55 @@
+use std::io::{Write, Result};
+
+use header::DType;
+use serializable::Serializable;
+
+/// Opaque raw bytes, mapping to Numpy's `|VN` type.
+///
+/// Unlike [`FixedBytes`](struct.FixedBytes.html), the bytes are kept exactly as they are, so
+/// blobs can be read and written back byte-for-byte.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Void<const N: usize>(pub [u8; N]);
+
+impl<const N: usize> Void<N> {
+    /// Returns the raw bytes
+    pub fn as_bytes(&self) -> &[u8; N] {
+        &self.0
+    }
+}
+
+impl<const N: usize> Default for Void<N> {
+    fn default() -> Self {
+        Void([0; N])
+    }
+}
+
+impl<const N: usize> From<[u8; N]> for Void<N> {
+    fn from(bytes: [u8; N]) -> Self {
+        Void(bytes)
+    }
+}
+
+impl<const N: usize> From<Void<N>> for [u8; N] {
+    fn from(void: Void<N>) -> Self {
+        void.0
+    }
+}
+
+impl<const N: usize> Serializable for Void<N> {
+    #[inline]
+    fn dtype() -> DType {
+        DType::Plain { ty: format!("|V{}", N), shape: vec![] }
+    }
+    #[inline]
+    fn n_bytes() -> usize { N }
+    #[inline]
+    fn read(buf: &[u8]) -> Self {
+        let mut bytes = [0; N];
+        bytes.copy_from_slice(&buf[..N]);
+        Void(bytes)
+    }
+    #[inline]
+    fn write<W: Write>(&self, writer: &mut W) -> Result<()> {
+        writer.write_all(&self.0)
+    }
+}
@@ -2,11 +2,17 @@
 extern crate npy_derive;
 extern crate npy;
 extern crate byteorder;
+#[cfg(feature = "num-complex")]
+extern crate num_complex;
+#[cfg(feature = "half")]
+extern crate half;
 
 use byteorder::ByteOrder;
 use std::io::{Read, Write};
-use byteorder::{WriteBytesExt, LittleEndian};
-use npy::{DType, Serializable};
+use byteorder::{WriteBytesExt, BigEndian, LittleEndian};
+use std::convert::TryFrom;
+use npy::{Complex, DType, Field, FixedBytes, FixedStr, Header, Order, Serializable, Version};
+use npy::{DateTime64, TimeDelta64, Microseconds, Nanoseconds, Void, ObjectArray, PyObject};
 
 #[derive(Serializable, Debug, PartialEq, Clone)]
 struct Nested {
@@ -14,6 +20,159 @@ struct Nested {
     v2: f32,
 }
 
+#[derive(Serializable, Debug, PartialEq, Clone)]
+struct Aligned {
+    a: u8,
+    b: f64,
+}
+
+#[derive(Serializable, Debug, PartialEq, Clone)]
+struct Reordered {
+    b: f64,
+    a: u8,
+}
+
+#[derive(Serializable, Debug, PartialEq, Clone)]
+struct Flagged {
+    value: i16,
+    valid: bool,
+}
+
+#[derive(Serializable, Debug, PartialEq, Clone)]
+struct Spectrum {
+    frequency: f64,
+    amplitude: Complex<f32>,
+    harmonics: [Complex<f64>; 2],
+}
+
+#[derive(Serializable, Debug, PartialEq, Clone)]
+struct Labeled {
+    id: FixedBytes<8>,
+    label: FixedStr<4>,
+}
+
+#[derive(Serializable, Debug, PartialEq, Clone)]
+struct Event {
+    time: DateTime64<Nanoseconds>,
+    duration: TimeDelta64<Microseconds>,
+}
+
+#[derive(Serializable, Debug, PartialEq, Clone)]
+struct Blob {
+    key: u16,
+    payload: Void<5>,
+}
+
+#[derive(Serializable, Debug, PartialEq, Clone)]
+struct Embedding {
+    hash: [u8; 64],
+    values: [f32; 128],
+    vectors: [Vector5; 2],
+}
+
+#[derive(Serializable, Debug, PartialEq, Clone)]
+struct Sparse {
+    id: u32,
+    empty: [f32; 0],
+}
+
+#[derive(Serializable, Debug, PartialEq, Clone)]
+struct Polygon {
+    id: u32,
+    points: [Nested; 3],
+    grid: [[Nested; 2]; 2],
+}
+
+#[derive(Serializable, Debug, PartialEq, Clone, Copy)]
+#[repr(u8)]
+enum Label {
+    Cat = 1,
+    Dog = 2,
+    Bird = 5,
+}
+
+#[derive(Serializable, Debug, PartialEq, Clone, Copy)]
+#[repr(i16)]
+enum Grade {
+    Low = -1,
+    High = 1,
+    #[npy(other)]
+    Unknown = 0,
+}
+
+#[derive(Serializable, Debug, PartialEq, Clone)]
+struct Sample {
+    label: Label,
+    grade: Grade,
+}
+
+#[derive(Serializable, Debug, PartialEq, Clone, Copy)]
+struct Meters(f64);
+
+#[derive(Serializable, Debug, PartialEq, Clone)]
+struct Segment(Meters, Meters, #[npy(rename = "label")] Label);
+
+#[derive(Serializable, Debug, PartialEq, Clone)]
+#[npy(rename_all = "camelCase")]
+struct Measurement {
+    time_stamp: i64,
+    #[npy(rename = "type")]
+    kind: u8,
+    #[npy(skip)]
+    cached_total: Option<f64>,
+    sensor_value: f32,
+}
+
+#[derive(Serializable, Debug, PartialEq, Clone)]
+#[npy(rename_all = "camelCase")]
+struct Keywords {
+    r#type: u8,
+    r#type_id: u16,
+}
+
+#[derive(Serializable, Debug, PartialEq, Clone)]
+struct MeasurementV2 {
+    #[npy(rename = "sensorValue")]
+    value: f32,
+    #[npy(rename = "timeStamp")]
+    time: i64,
+    #[npy(default)]
+    unit: FixedStr<2>,
+    nested: NestedV2,
+}
+
+#[derive(Serializable, Debug, PartialEq, Clone)]
+struct NestedV2 {
+    v2: f32,
+    #[npy(default)]
+    v3: u16,
+}
+
+#[derive(Serializable, Debug, PartialEq, Clone)]
+#[npy(rename_all = "camelCase")]
+struct WithNested {
+    time_stamp: i64,
+    nested: Nested,
+    sensor_value: f32,
+}
+
+#[derive(Serializable, Debug, PartialEq, Clone)]
+struct Point<T> {
+    x: T,
+    y: T,
+}
+
+#[derive(Serializable, Debug, PartialEq, Clone)]
+struct Tagged<T, const N: usize>(Point<T>, [u8; N]);
+
+#[derive(Serializable, Debug, PartialEq, Clone)]
+#[npy(bound = "T: Serializable + Default")]
+struct Cached<T> {
+    value: T,
+    #[npy(skip)]
+    previous: T,
+}
+
 #[derive(Serializable, Debug, PartialEq, Clone)]
 struct Array {
     v_i8: i8,
@@ -26,6 +185,7 @@ struct Array {
     v_u64: u64,
     v_f32: f32,
     v_f64: f64,
+    v_bool: bool,
     v_arr_u32: [u32;7],
     v_mat_u64: [[u64; 3]; 5],
     vec: Vector5,
@@ -64,6 +224,51 @@ impl Serializable for Vector5 {
     }
 }
 
+const N_WIDE: usize = 3000;
+
+/// A record with so many fields that its header doesn't fit into format version 1.0
+#[derive(Debug, PartialEq, Clone)]
+struct Wide(Vec<u8>);
+
+impl Serializable for Wide {
+    fn dtype() -> DType {
+        DType::Record((0..N_WIDE).map(|i| Field {
+            name: format!("field_with_a_rather_long_name_{}", i),
+            dtype: u8::dtype(),
+        }).collect())
+    }
+
+    fn n_bytes() -> usize { N_WIDE }
+
+    fn read(buf: &[u8]) -> Self {
+        Wide(buf[..N_WIDE].to_vec())
+    }
+
+    fn write<W: Write>(&self, writer: &mut W) -> std::io::Result<()> {
+        writer.write_all(&self.0)
+    }
+}
+
+/// A record with a field name that can't be encoded in Latin-1
+#[derive(Debug, PartialEq, Clone)]
+struct Unicode(f64);
+
+impl Serializable for Unicode {
+    fn dtype() -> DType {
+        DType::Record(vec![Field { name: "\u{6e29}\u{5ea6}".to_string(), dtype: f64::dtype() }])
+    }
+
+    fn n_bytes() -> usize { 8 }
+
+    fn read(buf: &[u8]) -> Self {
+        Unicode(f64::read(buf))
+    }
+
+    fn write<W: Write>(&self, writer: &mut W) -> std::io::Result<()> {
+        self.0.write(writer)
+    }
+}
+
 #[test]
 fn roundtrip() {
     let n = 100i64;
@@ -76,13 +281,14 @@ fn roundtrip() {
             v_i8: i as i8,
             v_i16: i as i16,
             v_i32: i as i32,
-            v_i64: i as i64,
+            v_i64: i,
             v_u8: i as u8,
             v_u16: i as u16,
             v_u32: i as u32,
             v_u64: i as u64,
             v_f32: i as f32,
             v_f64: i as f64,
+            v_bool: i % 3 == 0,
             v_arr_u32: [j,1+j,2+j,3+j,4+j,5+j,6+j],
             v_mat_u64: [[k,1+k,2+k],[3+k,4+k,5+k],[6+k,7+k,8+k],[9+k,10+k,11+k],[12+k,13+k,14+k]],
             vec: Vector5(vec![1,2,3,4,5]),
@@ -101,6 +307,216 @@ fn roundtrip() {
     assert_eq!(arrays, arrays2);
 }
 
+#[test]
+fn roundtrip_with_long_arrays() {
+    let array_written = vec![Embedding {
+        hash: [0xab; 64],
+        values: std::array::from_fn(|i| i as f32 / 2.0),
+        vectors: [Vector5(vec![1, 2, 3, 4, 5]), Vector5(vec![-1, -2, -3, -4, -5])],
+    }];
+    assert_eq!(Embedding::dtype().descr(),
+               "[('hash', '|u1', (64,)), ('values', '<f4', (128,)), ('vectors', '<i4', (2,5,)), ]");
+
+    npy::to_file("tests/roundtrip_long_arrays.npy", array_written.clone()).unwrap();
+
+    let mut buffer = vec![];
+    std::fs::File::open("tests/roundtrip_long_arrays.npy").unwrap()
+        .read_to_end(&mut buffer).unwrap();
+
+    let array_read = npy::NpyData::<Embedding>::from_bytes(&buffer).unwrap().to_vec();
+    assert_eq!(array_written, array_read);
+}
+
+#[test]
+fn roundtrip_with_empty_arrays() {
+    let array_written = vec![Sparse { id: 1, empty: [] }, Sparse { id: 2, empty: [] }];
+    assert_eq!(Sparse::dtype().descr(), "[('id', '<u4'), ('empty', '<f4', (0,)), ]");
+
+    npy::to_file("tests/roundtrip_empty_arrays.npy", array_written.clone()).unwrap();
+
+    let mut buffer = vec![];
+    std::fs::File::open("tests/roundtrip_empty_arrays.npy").unwrap()
+        .read_to_end(&mut buffer).unwrap();
+
+    let array_read = npy::NpyData::<Sparse>::from_bytes(&buffer).unwrap().to_vec();
+    assert_eq!(array_written, array_read);
+}
+
+#[test]
+fn roundtrip_with_record_subarrays() {
+    let point = |v: f32| Nested { v1: v, v2: -v };
+    let array_written = vec![Polygon {
+        id: 7,
+        points: [point(1.0), point(2.0), point(3.0)],
+        grid: [[point(4.0), point(5.0)], [point(6.0), point(7.0)]],
+    }];
+    assert_eq!(Polygon::dtype().descr(),
+               "[('id', '<u4'), ('points', [('v1', '<f4'), ('v2', '<f4'), ], (3,)), \
+                 ('grid', [('v1', '<f4'), ('v2', '<f4'), ], (2,2,)), ]");
+
+    npy::to_file("tests/roundtrip_record_subarrays.npy", array_written.clone()).unwrap();
+
+    let mut buffer = vec![];
+    std::fs::File::open("tests/roundtrip_record_subarrays.npy").unwrap()
+        .read_to_end(&mut buffer).unwrap();
+
+    let array_read = npy::NpyData::<Polygon>::from_bytes(&buffer).unwrap().to_vec();
+    assert_eq!(array_written, array_read);
+}
+
+#[test]
+fn roundtrip_with_tuples() {
+    let array_written = vec![(1i32, 2.5f64, (true, [3u8; 2])), (-4, 0.0, (false, [0, 1]))];
+    assert_eq!(<(i32, f64, (bool, [u8; 2]))>::dtype().descr(),
+               "[('f0', '<i4'), ('f1', '<f8'), ('f2', [('f0', '|b1'), ('f1', '|u1', (2,)), ]), ]");
+
+    npy::to_file("tests/roundtrip_tuples.npy", array_written.clone()).unwrap();
+
+    let mut buffer = vec![];
+    std::fs::File::open("tests/roundtrip_tuples.npy").unwrap()
+        .read_to_end(&mut buffer).unwrap();
+
+    let array_read = npy::NpyData::<(i32, f64, (bool, [u8; 2]))>::from_bytes(&buffer).unwrap().to_vec();
+    assert_eq!(array_written, array_read);
+}
+
+#[test]
+fn roundtrip_with_enums() {
+    let array_written = vec![
+        Sample { label: Label::Cat, grade: Grade::High },
+        Sample { label: Label::Bird, grade: Grade::Low },
+    ];
+    assert_eq!(Sample::dtype().descr(), "[('label', '|u1'), ('grade', '<i2'), ]");
+
+    npy::to_file("tests/roundtrip_enums.npy", array_written.clone()).unwrap();
+
+    let mut buffer = vec![];
+    std::fs::File::open("tests/roundtrip_enums.npy").unwrap()
+        .read_to_end(&mut buffer).unwrap();
+
+    let array_read = npy::NpyData::<Sample>::from_bytes_strict(&buffer).unwrap().to_vec();
+    assert_eq!(array_written, array_read);
+}
+
+#[test]
+fn reads_unknown_enum_discriminants_as_fallback_or_error() {
+    let mut buffer = Header::new(Sample::dtype(), vec![1], false).unwrap().to_bytes().unwrap();
+    buffer.extend_from_slice(&[3, 7, 0]);
+
+    assert!(npy::NpyData::<Sample>::from_bytes_strict(&buffer).is_err());
+
+    let mut buffer = Header::new(Grade::dtype(), vec![1], false).unwrap().to_bytes().unwrap();
+    buffer.extend_from_slice(&[7, 0]);
+    let grades = npy::NpyData::<Grade>::from_bytes_strict(&buffer).unwrap();
+    assert_eq!(grades.to_vec(), vec![Grade::Unknown]);
+}
+
+#[test]
+fn reads_unknown_enum_discriminants_without_fallback_as_first_variant() {
+    let mut buffer = Header::new(Label::dtype(), vec![2], false).unwrap().to_bytes().unwrap();
+    buffer.extend_from_slice(&[1, 3]);
+    assert_eq!(npy::NpyData::<Label>::from_bytes(&buffer).unwrap().to_vec(), vec![Label::Cat, Label::Cat]);
+    let err = npy::NpyData::<Label>::from_bytes_strict(&buffer).err().unwrap();
+    assert_eq!(err.to_string(), "record 1: 3 is not a valid discriminant of Label");
+
+    let mut buffer = Header::new(Segment::dtype(), vec![1], false).unwrap().to_bytes().unwrap();
+    buffer.extend_from_slice(&[0; 16]);
+    buffer.push(3);
+    assert_eq!(npy::NpyData::<Segment>::from_bytes_by_name(&buffer).unwrap().to_vec(),
+               vec![Segment(Meters(0.0), Meters(0.0), Label::Cat)]);
+    assert!(npy::NpyData::<Segment>::from_bytes_strict(&buffer).is_err());
+}
+
+#[test]
+fn roundtrip_with_tuple_structs_and_newtypes() {
+    let array_written = vec![
+        Segment(Meters(0.5), Meters(1.5), Label::Dog),
+        Segment(Meters(-2.0), Meters(0.0), Label::Cat),
+    ];
+    assert_eq!(Meters::dtype(), f64::dtype());
+    assert_eq!(Segment::dtype().descr(), "[('f0', '<f8'), ('f1', '<f8'), ('label', '|u1'), ]");
+
+    npy::to_file("tests/roundtrip_tuple_structs.npy", array_written.clone()).unwrap();
+
+    let mut buffer = vec![];
+    std::fs::File::open("tests/roundtrip_tuple_structs.npy").unwrap()
+        .read_to_end(&mut buffer).unwrap();
+
+    let array_read = npy::NpyData::<Segment>::from_bytes(&buffer).unwrap().to_vec();
+    assert_eq!(array_written, array_read);
+}
+
+#[test]
+fn roundtrip_with_generic_structs() {
+    let array_written = vec![
+        Tagged(Point { x: 1.5f32, y: -2.0 }, [1, 2, 3]),
+        Tagged(Point { x: 0.0, y: 4.25 }, [0, 0, 9]),
+    ];
+    assert_eq!(Point::<i16>::dtype().descr(), "[('x', '<i2'), ('y', '<i2'), ]");
+    assert_eq!(Tagged::<f32, 3>::dtype().descr(),
+               "[('f0', [('x', '<f4'), ('y', '<f4'), ]), ('f1', '|u1', (3,)), ]");
+
+    npy::to_file("tests/roundtrip_generic.npy", array_written.clone()).unwrap();
+
+    let mut buffer = vec![];
+    std::fs::File::open("tests/roundtrip_generic.npy").unwrap()
+        .read_to_end(&mut buffer).unwrap();
+
+    let array_read = npy::NpyData::<Tagged<f32, 3>>::from_bytes(&buffer).unwrap().to_vec();
+    assert_eq!(array_written, array_read);
+
+    let mut buffer = vec![];
+    Cached { value: 7u32, previous: 3 }.write(&mut buffer).unwrap();
+    assert_eq!(Cached::<u32>::read(&buffer), Cached { value: 7, previous: 0 });
+}
+
+#[test]
+fn roundtrip_with_renamed_and_skipped_fields() {
+    let array_written = vec![
+        Measurement { time_stamp: 100, kind: 3, cached_total: Some(1.0), sensor_value: 0.5 },
+    ];
+    assert_eq!(Measurement::dtype().descr(),
+               "[('timeStamp', '<i8'), ('type', '|u1'), ('sensorValue', '<f4'), ]");
+    assert_eq!(Measurement::n_bytes(), 13);
+
+    npy::to_file("tests/roundtrip_renamed.npy", array_written).unwrap();
+
+    let mut buffer = vec![];
+    std::fs::File::open("tests/roundtrip_renamed.npy").unwrap()
+        .read_to_end(&mut buffer).unwrap();
+
+    let array_read = npy::NpyData::<Measurement>::from_bytes(&buffer).unwrap().to_vec();
+    assert_eq!(array_read, vec![
+        Measurement { time_stamp: 100, kind: 3, cached_total: None, sensor_value: 0.5 },
+    ]);
+}
+
+#[test]
+fn names_raw_identifier_fields_without_prefix() {
+    assert_eq!(Keywords::dtype().descr(), "[('type', '|u1'), ('typeId', '<u2'), ]");
+}
+
+#[test]
+fn reads_records_by_field_name() {
+    let written = WithNested { time_stamp: 5, nested: Nested { v1: 1.0, v2: 2.0 }, sensor_value: 0.25 };
+    let mut buffer = Header::new(WithNested::dtype(), vec![1], false).unwrap().to_bytes().unwrap();
+    written.write(&mut buffer).unwrap();
+
+    assert!(npy::NpyData::<MeasurementV2>::from_bytes(&buffer).is_err());
+    let data = npy::NpyData::<MeasurementV2>::from_bytes_by_name(&buffer).unwrap();
+    assert_eq!(data.to_vec(), vec![MeasurementV2 {
+        value: 0.25,
+        time: 5,
+        unit: FixedStr::default(),
+        nested: NestedV2 { v2: 2.0, v3: 0 },
+    }]);
+
+    // Fields without a default must be present
+    let buffer = Header::new(MeasurementV2::dtype(), vec![0], false).unwrap().to_bytes().unwrap();
+    let err = npy::NpyData::<WithNested>::from_bytes_by_name(&buffer).err().unwrap();
+    assert!(err.to_string().contains("field 'nested.v1' is missing"), "{}", err);
+}
+
 #[test]
 fn roundtrip_with_simple_dtype() {
     let array_written = vec![2., 3., 4., 5.];
@@ -114,3 +530,354 @@ fn roundtrip_with_simple_dtype() {
     let array_read = npy::NpyData::from_bytes(&buffer).unwrap().to_vec();
     assert_eq!(array_written, array_read);
 }
+
+#[test]
+fn roundtrip_with_header_longer_than_u16() {
+    let array_written = vec![Wide(vec![1; N_WIDE]), Wide((0..N_WIDE).map(|i| i as u8).collect())];
+
+    npy::to_file("tests/roundtrip_wide.npy", array_written.clone()).unwrap();
+
+    let mut buffer = vec![];
+    std::fs::File::open("tests/roundtrip_wide.npy").unwrap()
+        .read_to_end(&mut buffer).unwrap();
+
+    let data = npy::NpyData::from_bytes(&buffer).unwrap();
+    assert_eq!(data.version(), Version::V2);
+    assert_eq!(array_written, data.to_vec());
+}
+
+#[test]
+fn roundtrip_with_non_latin1_field_name() {
+    let array_written = vec![Unicode(1.5), Unicode(-2.0)];
+
+    npy::to_file("tests/roundtrip_unicode.npy", array_written.clone()).unwrap();
+
+    let mut buffer = vec![];
+    std::fs::File::open("tests/roundtrip_unicode.npy").unwrap()
+        .read_to_end(&mut buffer).unwrap();
+
+    let data = npy::NpyData::from_bytes(&buffer).unwrap();
+    assert_eq!(data.version(), Version::V3);
+    assert_eq!(array_written, data.to_vec());
+}
+
+#[test]
+fn roundtrip_with_fortran_order() {
+    {
+        let mut out = npy::OutFile::open_with_shape("tests/roundtrip_fortran.npy", &[2, 3], Order::Fortran).unwrap();
+        for x in &[0, 10, 1, 11, 2, 12] {
+            out.push(x).unwrap();
+        }
+        out.close().unwrap();
+    }
+
+    let mut buffer = vec![];
+    std::fs::File::open("tests/roundtrip_fortran.npy").unwrap()
+        .read_to_end(&mut buffer).unwrap();
+
+    let data = npy::NpyData::<i32>::from_bytes(&buffer).unwrap();
+    assert_eq!(data.order(), Order::Fortran);
+    assert_eq!(data.shape(), &[2, 3]);
+    assert_eq!(data.to_vec_c_order(), vec![0, 1, 2, 10, 11, 12]);
+}
+
+#[test]
+fn roundtrip_with_scalar_shape() {
+    {
+        let mut out = npy::OutFile::open_with_shape("tests/roundtrip_scalar.npy", &[], Order::C).unwrap();
+        out.push(&3.5).unwrap();
+        out.close().unwrap();
+    }
+
+    let mut buffer = vec![];
+    std::fs::File::open("tests/roundtrip_scalar.npy").unwrap()
+        .read_to_end(&mut buffer).unwrap();
+
+    let data = npy::NpyData::<f64>::from_bytes(&buffer).unwrap();
+    assert_eq!(data.shape(), &[] as &[u64]);
+    assert_eq!(data.to_vec(), vec![3.5]);
+}
+
+#[test]
+fn closing_errors_when_row_count_does_not_match_shape() {
+    let mut out = npy::OutFile::open_with_shape("tests/wrong_shape.npy", &[2, 2], Order::C).unwrap();
+    out.push(&1.0).unwrap();
+    assert!(out.close().is_err());
+}
+
+#[test]
+fn opening_errors_when_shape_overflows() {
+    let err = npy::OutFile::<f64>::open_with_shape("tests/overflowing_shape.npy", &[u64::MAX, 3], Order::C)
+        .err().unwrap();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+}
+
+#[test]
+fn reads_big_endian_records() {
+    let dtype = DType::Record(vec![
+        Field { name: "v1".to_string(), dtype: DType::Plain { ty: ">f4".to_string(), shape: vec![] } },
+        Field { name: "v2".to_string(), dtype: DType::Plain { ty: "<f4".to_string(), shape: vec![] } },
+    ]);
+    let mut buffer = Header::new(dtype, vec![2], false).unwrap().to_bytes().unwrap();
+    for &(v1, v2) in &[(1.5f32, 2.5f32), (-3.0, 4.0)] {
+        buffer.write_f32::<BigEndian>(v1).unwrap();
+        buffer.write_f32::<LittleEndian>(v2).unwrap();
+    }
+
+    let data = npy::NpyData::<Nested>::from_bytes(&buffer).unwrap();
+    assert_eq!(data.to_vec(), vec![Nested { v1: 1.5, v2: 2.5 }, Nested { v1: -3.0, v2: 4.0 }]);
+}
+
+#[test]
+fn reads_aligned_records_described_by_dict() {
+    let dtype = DType::OffsetRecord {
+        fields: vec![Field { name: "a".to_string(), dtype: u8::dtype() }, Field { name: "b".to_string(), dtype: f64::dtype() }],
+        offsets: vec![0, 8],
+        itemsize: 24,
+    };
+    let mut buffer = Header::new(dtype, vec![2], false).unwrap().to_bytes().unwrap();
+    for &(a, b) in &[(3u8, 0.5f64), (4, -1.5)] {
+        buffer.push(a);
+        buffer.extend(&[0xff; 7]);
+        buffer.write_f64::<LittleEndian>(b).unwrap();
+        buffer.extend(&[0xff; 8]);
+    }
+
+    let data = npy::NpyData::<Aligned>::from_bytes(&buffer).unwrap();
+    assert_eq!(data.to_vec(), vec![Aligned { a: 3, b: 0.5 }, Aligned { a: 4, b: -1.5 }]);
+}
+
+#[test]
+fn reads_records_with_fields_stored_out_of_order() {
+    let dtype = DType::OffsetRecord {
+        fields: vec![Field { name: "b".to_string(), dtype: f64::dtype() }, Field { name: "a".to_string(), dtype: u8::dtype() }],
+        offsets: vec![8, 0],
+        itemsize: 16,
+    };
+    let mut buffer = Header::new(dtype, vec![2], false).unwrap().to_bytes().unwrap();
+    for &(b, a) in &[(0.5f64, 3u8), (-1.5, 4)] {
+        buffer.push(a);
+        buffer.extend(&[0xff; 7]);
+        buffer.write_f64::<LittleEndian>(b).unwrap();
+    }
+
+    let expected = vec![Reordered { b: 0.5, a: 3 }, Reordered { b: -1.5, a: 4 }];
+    assert_eq!(npy::NpyData::<Reordered>::from_bytes(&buffer).unwrap().to_vec(), expected);
+    assert_eq!(npy::NpyData::<Reordered>::from_bytes_by_name(&buffer).unwrap().to_vec(), expected);
+    assert!(npy::NpyData::<Aligned>::from_bytes(&buffer).is_err());
+    assert_eq!(npy::NpyData::<Aligned>::from_bytes_by_name(&buffer).unwrap().to_vec(),
+               vec![Aligned { a: 3, b: 0.5 }, Aligned { a: 4, b: -1.5 }]);
+}
+
+#[test]
+fn reads_bools_leniently_or_strictly() {
+    let mut buffer = Header::new(bool::dtype(), vec![3], false).unwrap().to_bytes().unwrap();
+    buffer.extend(&[0, 1, 2]);
+
+    let data = npy::NpyData::<bool>::from_bytes(&buffer).unwrap();
+    assert_eq!(data.to_vec(), vec![false, true, true]);
+    assert!(npy::NpyData::<bool>::from_bytes_strict(&buffer).is_err());
+
+    buffer.pop();
+    buffer.push(1);
+    let data = npy::NpyData::<bool>::from_bytes_strict(&buffer).unwrap();
+    assert_eq!(data.to_vec(), vec![false, true, true]);
+}
+
+#[test]
+fn validates_bool_fields_of_records() {
+    let mut buffer = Header::new(Flagged::dtype(), vec![2], false).unwrap().to_bytes().unwrap();
+    buffer.extend(&[1, 0, 1, 2, 0, 7]);
+
+    let data = npy::NpyData::<Flagged>::from_bytes(&buffer).unwrap();
+    assert_eq!(data.to_vec(), vec![Flagged { value: 1, valid: true }, Flagged { value: 2, valid: true }]);
+    assert!(npy::NpyData::<Flagged>::from_bytes_strict(&buffer).is_err());
+}
+
+#[test]
+fn roundtrip_with_complex_numbers() {
+    let array_written = vec![
+        Spectrum {
+            frequency: 50.0,
+            amplitude: Complex::new(1.0, -1.0),
+            harmonics: [Complex::new(0.5, 0.25), [0.125, -2.0].into()],
+        },
+    ];
+    assert_eq!(Spectrum::dtype().descr(),
+               "[('frequency', '<f8'), ('amplitude', '<c8'), ('harmonics', '<c16', (2,)), ]");
+
+    npy::to_file("tests/roundtrip_complex.npy", array_written.clone()).unwrap();
+
+    let mut buffer = vec![];
+    std::fs::File::open("tests/roundtrip_complex.npy").unwrap()
+        .read_to_end(&mut buffer).unwrap();
+
+    let array_read = npy::NpyData::from_bytes(&buffer).unwrap().to_vec();
+    assert_eq!(array_written, array_read);
+}
+
+#[test]
+fn reads_big_endian_complex_numbers() {
+    let dtype = DType::Plain { ty: ">c16".to_string(), shape: vec![] };
+    let mut buffer = Header::new(dtype, vec![1], false).unwrap().to_bytes().unwrap();
+    buffer.write_f64::<BigEndian>(1.5).unwrap();
+    buffer.write_f64::<BigEndian>(-0.5).unwrap();
+
+    let data = npy::NpyData::<Complex<f64>>::from_bytes(&buffer).unwrap();
+    assert_eq!(data.to_vec(), vec![Complex::new(1.5, -0.5)]);
+}
+
+#[test]
+fn roundtrip_with_fixed_length_strings() {
+    let array_written = vec![
+        Labeled {
+            id: FixedBytes::try_from(&b"a1b2"[..]).unwrap(),
+            label: FixedStr::try_from("\u{17e}lut").unwrap(),
+        },
+        Labeled { id: FixedBytes::default(), label: FixedStr::default() },
+    ];
+    assert_eq!(Labeled::dtype().descr(), "[('id', '|S8'), ('label', '<U4'), ]");
+
+    npy::to_file("tests/roundtrip_strings.npy", array_written.clone()).unwrap();
+
+    let mut buffer = vec![];
+    std::fs::File::open("tests/roundtrip_strings.npy").unwrap()
+        .read_to_end(&mut buffer).unwrap();
+
+    let array_read = npy::NpyData::<Labeled>::from_bytes(&buffer).unwrap().to_vec();
+    assert_eq!(array_written, array_read);
+    assert_eq!(array_read[0].id.as_bytes(), b"a1b2");
+    assert_eq!(String::from(array_read[0].label), "\u{17e}lut");
+}
+
+#[test]
+fn reads_big_endian_unicode_strings() {
+    let dtype = DType::Plain { ty: ">U2".to_string(), shape: vec![] };
+    let mut buffer = Header::new(dtype, vec![1], false).unwrap().to_bytes().unwrap();
+    buffer.write_u32::<BigEndian>('h' as u32).unwrap();
+    buffer.write_u32::<BigEndian>('\u{e9}' as u32).unwrap();
+
+    let data = npy::NpyData::<FixedStr<2>>::from_bytes(&buffer).unwrap();
+    assert_eq!(data.get(0).unwrap().to_string(), "h\u{e9}");
+}
+
+#[test]
+fn roundtrip_with_datetimes() {
+    let array_written = vec![
+        Event { time: DateTime64::new(1_500_000_000_000_000_000), duration: TimeDelta64::new(-250) },
+        Event { time: DateTime64::NAT, duration: TimeDelta64::NAT },
+    ];
+    assert_eq!(Event::dtype().descr(), "[('time', '<M8[ns]'), ('duration', '<m8[us]'), ]");
+
+    npy::to_file("tests/roundtrip_datetime.npy", array_written.clone()).unwrap();
+
+    let mut buffer = vec![];
+    std::fs::File::open("tests/roundtrip_datetime.npy").unwrap()
+        .read_to_end(&mut buffer).unwrap();
+
+    let array_read = npy::NpyData::<Event>::from_bytes(&buffer).unwrap().to_vec();
+    assert_eq!(array_written, array_read);
+    assert_eq!(array_read[1].time.get(), None);
+}
+
+#[test]
+fn errors_on_mismatched_time_unit() {
+    let mut buffer = Header::new(<DateTime64<Microseconds>>::dtype(), vec![1], false).unwrap().to_bytes().unwrap();
+    DateTime64::<Microseconds>::new(7).write(&mut buffer).unwrap();
+
+    assert!(npy::NpyData::<DateTime64<Nanoseconds>>::from_bytes(&buffer).is_err());
+    assert!(npy::NpyData::<DateTime64<Microseconds>>::from_bytes(&buffer).is_ok());
+
+    let dtype = DType::Plain { ty: "<M8[Y]".to_string(), shape: vec![] };
+    let mut buffer = Header::new(dtype, vec![1], false).unwrap().to_bytes().unwrap();
+    buffer.extend_from_slice(&[0; 8]);
+    let err = npy::NpyData::<DateTime64<Nanoseconds>>::from_bytes(&buffer).err().unwrap();
+    assert!(err.to_string().contains("unsupported datetime unit 'Y'"), "{}", err);
+}
+
+#[test]
+fn roundtrip_with_void_fields() {
+    let array_written = vec![
+        Blob { key: 1, payload: Void([0, 0xff, 0, 3, 0]) },
+        Blob { key: 2, payload: [7; 5].into() },
+    ];
+    assert_eq!(Blob::dtype().descr(), "[('key', '<u2'), ('payload', '|V5'), ]");
+
+    npy::to_file("tests/roundtrip_void.npy", array_written.clone()).unwrap();
+
+    let mut buffer = vec![];
+    std::fs::File::open("tests/roundtrip_void.npy").unwrap()
+        .read_to_end(&mut buffer).unwrap();
+
+    let array_read = npy::NpyData::<Blob>::from_bytes(&buffer).unwrap().to_vec();
+    assert_eq!(array_written, array_read);
+
+    let mut rewritten = vec![];
+    for blob in &array_read {
+        blob.write(&mut rewritten).unwrap();
+    }
+    assert_eq!(&buffer[buffer.len() - rewritten.len()..], &rewritten[..]);
+}
+
+#[test]
+fn reads_pickled_object_arrays() {
+    let dtype = DType::Plain { ty: "|O".to_string(), shape: vec![] };
+    let mut buffer = Header::new(dtype, vec![3], false).unwrap().to_bytes().unwrap();
+    // `numpy.array(['ab', None, 3], dtype=object)`, pickled by `numpy.save`
+    buffer.extend_from_slice(b"\x80\x03cnumpy.core.multiarray\n_reconstruct\nq\x00cnumpy\nndarray\nq\x01K\x00\
+        \x85q\x02C\x01bq\x03\x87q\x04Rq\x05(K\x01K\x03\x85q\x06cnumpy\ndtype\nq\x07X\x02\x00\x00\x00O8q\x08\
+        \x89\x88\x87q\tRq\n(K\x03X\x01\x00\x00\x00|q\x0bNNNJ\xff\xff\xff\xffJ\xff\xff\xff\xffK?tq\x0cb\
+        \x89]q\r(X\x02\x00\x00\x00abq\x0eNK\x03etq\x0fb.");
+
+    let array = ObjectArray::from_bytes(&buffer).unwrap();
+    assert_eq!(array.shape(), &[3]);
+    assert_eq!(array.into_vec(), vec![PyObject::Str("ab".to_string()), PyObject::None, PyObject::Int(3)]);
+
+    let err = npy::NpyData::<f64>::from_bytes(&buffer).err().unwrap();
+    assert!(err.to_string().contains("ObjectArray"));
+}
+
+#[cfg(feature = "num-complex")]
+#[test]
+fn reads_num_complex_numbers() {
+    let mut buffer = Header::new(<num_complex::Complex<f32>>::dtype(), vec![1], false).unwrap().to_bytes().unwrap();
+    Complex::new(2.0f32, 3.0).write(&mut buffer).unwrap();
+
+    let data = npy::NpyData::<num_complex::Complex<f32>>::from_bytes(&buffer).unwrap();
+    assert_eq!(data.to_vec(), vec![num_complex::Complex::new(2.0, 3.0)]);
+}
+
+#[cfg(feature = "half")]
+#[test]
+fn roundtrip_with_half_precision_floats() {
+    use half::f16;
+
+    let array_written = vec![f16::from_f32(1.5), f16::from_f32(-0.25), f16::INFINITY];
+    assert_eq!(f16::dtype().descr(), "'<f2'");
+
+    npy::to_file("tests/roundtrip_half.npy", array_written.clone()).unwrap();
+
+    let mut buffer = vec![];
+    std::fs::File::open("tests/roundtrip_half.npy").unwrap()
+        .read_to_end(&mut buffer).unwrap();
+
+    let array_read = npy::NpyData::<f16>::from_bytes(&buffer).unwrap().to_vec();
+    assert_eq!(array_written, array_read);
+}
+
+#[cfg(feature = "bf16")]
+#[test]
+fn stores_bfloat16_as_uint16() {
+    use half::bf16;
+
+    let array_written = vec![bf16::from_f32(1.5), bf16::from_f32(-3.0)];
+    npy::to_file("tests/roundtrip_bf16.npy", array_written.clone()).unwrap();
+
+    let mut buffer = vec![];
+    std::fs::File::open("tests/roundtrip_bf16.npy").unwrap()
+        .read_to_end(&mut buffer).unwrap();
+
+    let bits = npy::NpyData::<u16>::from_bytes(&buffer).unwrap().to_vec();
+    assert_eq!(bits, array_written.iter().map(|x| x.to_bits()).collect::<Vec<_>>());
+    assert_eq!(npy::NpyData::<bf16>::from_bytes(&buffer).unwrap().to_vec(), array_written);
+}